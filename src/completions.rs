@@ -0,0 +1,246 @@
+//! Shell completion scripts. The scripts are generated from the CLI definition,
+//! with extra functions that complete whatdo IDs and tags by calling
+//! `wd __complete` on the whatdo file in use, including one given with `--file`.
+
+use std::collections::BTreeSet;
+use std::io::Write;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use clap_complete::Shell;
+
+use crate::core::Whatdo;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionKind {
+    Ids,
+    Tags,
+    Branches,
+}
+
+fn collect_candidates(wd: &Whatdo, kind: CompletionKind, candidates: &mut BTreeSet<String>) {
+    for subwhatdo in wd.whatdos() {
+        match kind {
+            CompletionKind::Ids => {
+                candidates.insert(subwhatdo.id.clone());
+            }
+            CompletionKind::Tags => candidates.extend(subwhatdo.tags.iter().flatten().cloned()),
+            CompletionKind::Branches => {
+                candidates.insert(subwhatdo.branch_name().clone());
+            }
+        }
+        collect_candidates(&subwhatdo, kind, candidates);
+    }
+}
+
+/// The IDs, tags or branch names of all whatdos under the root, sorted
+pub fn candidates(root: &Whatdo, kind: CompletionKind) -> Vec<String> {
+    let mut candidates = BTreeSet::new();
+    collect_candidates(root, kind, &mut candidates);
+    candidates.into_iter().collect()
+}
+
+// The subcommand is the first word that isn't an option or an option's value.
+// VALUE_OPTIONS is replaced with the options that take a value
+const BASH_DYNAMIC: &str = r#"
+_wd_dynamic() {
+    local cur prev word subcommand first_arg kind i args=0
+    local -a file_args
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+    for (( i = 1; i < COMP_CWORD; i++ )); do
+        word="${COMP_WORDS[i]}"
+        case "${word}" in
+            -f|--file) file_args=(--file "${COMP_WORDS[i+1]}"); (( i++ )) ;;
+            --file=*) file_args=("${word}") ;;
+            VALUE_OPTIONS) (( i++ )) ;;
+            -*) ;;
+            *)
+                if [[ -z "${subcommand}" ]]; then
+                    subcommand="${word}"
+                else
+                    (( args++ ))
+                    [[ ${args} -eq 1 ]] && first_arg="${word}"
+                fi ;;
+        esac
+    done
+    case "${prev}" in
+        -r|--parent|--in) kind=ids ;;
+        -t|--tags|-T|--remove-tags) kind=tags ;;
+    esac
+    if [[ -z "${kind}" && "${cur}" != -* ]]; then
+        case "${subcommand}" in
+            start|switch|rm|delete|resolve|edit|mv|show|ls)
+                [[ ${args} -eq 0 ]] && kind=ids ;;
+            queue)
+                [[ ${args} -eq 1 && "${first_arg}" != ls ]] && kind=ids ;;
+        esac
+    fi
+    if [[ -n "${kind}" ]]; then
+        COMPREPLY=( $(compgen -W "$(wd "${file_args[@]}" __complete "${kind}" 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+    _wd "$@"
+}
+
+complete -F _wd_dynamic -o bashdefault -o default wd
+"#;
+
+const ZSH_DYNAMIC: &str = r#"
+_wd_dynamic() {
+    local word subcommand first_arg kind i args=0
+    local -a file_args
+    for (( i = 2; i < CURRENT; i++ )); do
+        word="${words[i]}"
+        case "${word}" in
+            -f|--file) file_args=(--file "${words[i+1]}"); (( i++ )) ;;
+            --file=*) file_args=("${word}") ;;
+            VALUE_OPTIONS) (( i++ )) ;;
+            -*) ;;
+            *)
+                if [[ -z "${subcommand}" ]]; then
+                    subcommand="${word}"
+                else
+                    (( args++ ))
+                    (( args == 1 )) && first_arg="${word}"
+                fi ;;
+        esac
+    done
+    case "${words[CURRENT-1]}" in
+        -r|--parent|--in) kind=ids ;;
+        -t|--tags|-T|--remove-tags) kind=tags ;;
+    esac
+    if [[ -z "${kind}" && "${words[CURRENT]}" != -* ]]; then
+        case "${subcommand}" in
+            start|switch|rm|delete|resolve|edit|mv|show|ls)
+                (( args == 0 )) && kind=ids ;;
+            queue)
+                (( args == 1 )) && [[ "${first_arg}" != ls ]] && kind=ids ;;
+        esac
+    fi
+    if [[ -n "${kind}" ]]; then
+        local -a candidates
+        candidates=(${(f)"$(wd "${file_args[@]}" __complete "${kind}" 2>/dev/null)"})
+        compadd -a candidates
+        return
+    fi
+    _wd "$@"
+}
+
+compdef _wd_dynamic wd
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+function __wd_complete
+    set -l tokens (commandline -opc)
+    set -l file_args
+    for i in (seq 2 (count $tokens))
+        switch $tokens[$i]
+            case -f --file
+                set file_args --file $tokens[(math $i + 1)]
+            case '--file=*'
+                set file_args $tokens[$i]
+        end
+    end
+    wd $file_args __complete $argv 2>/dev/null
+end
+
+complete -c wd -n "__fish_seen_subcommand_from start switch rm delete resolve edit mv show ls" -f -a "(__wd_complete ids)"
+complete -c wd -s r -l parent -l in -x -a "(__wd_complete ids)"
+complete -c wd -s t -l tags -s T -l remove-tags -x -a "(__wd_complete tags)"
+"#;
+
+/// The short and long forms of the options of the command and its subcommands
+/// that take a value
+fn value_options(cmd: &clap::Command, options: &mut BTreeSet<String>) {
+    for arg in cmd.get_arguments() {
+        if arg.is_positional() || !arg.get_action().takes_values() {
+            continue;
+        }
+        let shorts = arg.get_short_and_visible_aliases().unwrap_or_default();
+        options.extend(shorts.iter().map(|short| format!("-{}", short)));
+        let longs = arg.get_long_and_visible_aliases().unwrap_or_default();
+        options.extend(longs.iter().map(|long| format!("--{}", long)));
+    }
+    for subcommand in cmd.get_subcommands() {
+        value_options(subcommand, options);
+    }
+}
+
+/// Write the completion script for the shell
+pub fn generate(shell: Shell, cmd: &mut clap::Command, out: &mut impl Write) -> Result<()> {
+    cmd.build();
+    let mut options = BTreeSet::new();
+    value_options(cmd, &mut options);
+    let options = options.into_iter().collect::<Vec<_>>().join("|");
+
+    clap_complete::generate(shell, cmd, "wd", out);
+    let dynamic = match shell {
+        Shell::Bash => BASH_DYNAMIC,
+        Shell::Zsh => ZSH_DYNAMIC,
+        Shell::Fish => FISH_DYNAMIC,
+        _ => "",
+    };
+    out.write_all(dynamic.replace("VALUE_OPTIONS", &options).as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::Path;
+
+    #[test]
+    fn test_candidates() {
+        let root = crate::core::root(Path::new("./test_data/sort_test.yaml"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            candidates(&root, CompletionKind::Ids),
+            vec![
+                "advanced_functionality",
+                "basic-functionality",
+                "delete-whatdo",
+                "finish-whatdo",
+                "less-fossil-fuels",
+                "more-green-energy",
+                "read-back-whatdos",
+                "read-users-mind",
+                "solve-climate-change",
+            ]
+        );
+        assert_eq!(
+            candidates(&root, CompletionKind::Tags),
+            vec!["todo", "todont"]
+        );
+    }
+
+    #[test]
+    fn test_generate() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let mut out = Vec::new();
+            let show = clap::Command::new("show")
+                .arg(clap::Arg::new("sort").long("sort"))
+                .arg(
+                    clap::Arg::new("extra")
+                        .long("extra")
+                        .action(clap::ArgAction::SetTrue),
+                );
+            generate(
+                shell,
+                &mut clap::Command::new("wd").subcommand(show),
+                &mut out,
+            )
+            .unwrap();
+            let script = String::from_utf8(out).unwrap();
+            assert!(script.contains("show"), "{}", script);
+            if shell != Shell::Fish {
+                assert!(script.contains("--sort) (( i++ ))"), "{}", script);
+                assert!(!script.contains("VALUE_OPTIONS"), "{}", script);
+            }
+            assert!(script.contains("__complete"), "{}", script);
+            assert!(script.contains("--file"), "{}", script);
+        }
+    }
+}
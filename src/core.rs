@@ -1,15 +1,18 @@
-use super::{git, sample};
+use super::{git, query::Query, sample, yaml_edit};
 use anyhow::{Error, Result};
+use chrono::{DateTime, Datelike, Local, NaiveDate, SecondsFormat, Utc};
 use colored::Colorize;
 use core::fmt;
 use log::warn;
 use once_cell::sync::Lazy;
+use serde_json::json;
 use serde_yaml::{Mapping, Number};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
 use std::path::{Component, Path};
+use std::str::FromStr;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Whatdo {
     pub id: String,
     pub summary: Option<String>,
@@ -19,6 +22,18 @@ pub struct Whatdo {
     pub tags: Option<Vec<String>>,
     pub branch_name: Option<String>,
     pub simple_format: bool,
+    /// File the body of this whatdo is stored in, relative to the file that includes it
+    pub include: Option<PathBuf>,
+    /// Directory holding one file per sub-whatdo, relative to the file containing this whatdo
+    pub whatdos_dir: Option<PathBuf>,
+    /// IDs of whatdos that must be resolved before this one can be started
+    pub blocked_by: Option<Vec<String>>,
+    /// Any other keys of the whatdo's mapping, which are kept as-is
+    pub extra: Mapping,
+    pub created: Option<NaiveDate>,
+    /// Date the whatdo was last changed with `wd edit`
+    pub updated: Option<NaiveDate>,
+    pub due: Option<NaiveDate>,
 }
 
 fn deslugify(s: &str) -> String {
@@ -46,12 +61,8 @@ impl Whatdo {
         Whatdo {
             id: id.into(),
             summary: summary.map(|s| s.into()),
-            whatdos: None,
-            queue: None,
-            priority: None,
-            tags: None,
-            branch_name: None,
             simple_format: true,
+            ..Default::default()
         }
     }
 
@@ -75,16 +86,50 @@ impl Whatdo {
             && self.whatdos.is_none()
             && self.priority.is_none()
             && self.tags.is_none()
+            && self.include.is_none()
+            && self.whatdos_dir.is_none()
+            && self.blocked_by.is_none()
+            && self.extra.is_empty()
+            && self.created.is_none()
+            && self.updated.is_none()
+            && self.due.is_none()
     }
 
     pub fn branch_name(&self) -> &String {
         self.branch_name.as_ref().unwrap_or(&self.id)
     }
+
+    /// The earliest due date of the whatdo and its sub-whatdos
+    fn earliest_due_date(&self) -> Option<NaiveDate> {
+        self.whatdos
+            .iter()
+            .flatten()
+            .filter_map(|wd| wd.earliest_due_date())
+            .chain(self.due)
+            .min()
+    }
+
+    /// The earliest due date of the whatdo and its sub-whatdos, if it is
+    /// overdue or will be due soon
+    fn soon_due_date(&self) -> Option<NaiveDate> {
+        self.earliest_due_date()
+            .filter(|due| (*due - today()).num_days() <= DUE_SOON_DAYS)
+    }
+
+    /// Number of days until the whatdo is due, which is negative if it is overdue
+    pub fn days_until_due(&self) -> Option<i64> {
+        self.due.map(|due| (due - today()).num_days())
+    }
 }
 
 impl fmt::Display for Whatdo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{}]", self.id.yellow())?;
+        let days_until_due = self.days_until_due();
+        if days_until_due.is_some_and(|days| days < 0) {
+            write!(f, "[{}]", self.id.red().bold())?;
+        } else {
+            write!(f, "[{}]", self.id.yellow())?;
+        }
         if let Some(p) = self.priority {
             write!(f, " [P{}]", p.to_string().bold())?;
         }
@@ -100,6 +145,18 @@ impl fmt::Display for Whatdo {
             }
             write!(f, "]")?;
         }
+        match days_until_due {
+            None => {}
+            Some(0) => write!(f, " [{}]", "due today".bold())?,
+            Some(1) => write!(f, " [{}]", "due tomorrow".bold())?,
+            Some(days) if days > 0 => write!(f, " [due in {} days]", days)?,
+            Some(-1) => write!(f, " [{}]", "overdue by 1 day".red().bold())?,
+            Some(days) => write!(
+                f,
+                " [{}]",
+                format!("overdue by {} days", -days).red().bold()
+            )?,
+        }
         write!(f, " {}", self.summary())
     }
 }
@@ -109,6 +166,99 @@ pub struct WhatdoTreeView {
     pub filter: Box<dyn Fn(&Whatdo) -> bool>,
     // If true, all children of selected nodes will be printed
     pub transitive: bool,
+    // If true, the extra fields of selected nodes will be printed below them
+    pub show_extra: bool,
+    // If set, sibling whatdos are printed in this order instead of file order
+    pub sort: Option<SortOrder>,
+    // If set, only this many levels below selected nodes are printed, and deeper
+    // whatdos are summarized
+    pub max_depth: Option<usize>,
+}
+
+/// Counts of the whatdos under a whatdo, for summarizing them in one line
+#[derive(Default)]
+struct DescendantCounts {
+    descendants: usize,
+    leaves: usize,
+    tags: BTreeMap<String, usize>,
+    priorities: BTreeMap<i64, usize>,
+}
+
+fn collect_descendant_counts(wd: &Whatdo, counts: &mut DescendantCounts) {
+    for subwhatdo in wd.whatdos() {
+        counts.descendants += 1;
+        if subwhatdo.whatdos().is_empty() {
+            counts.leaves += 1;
+        }
+        for tag in subwhatdo.tags.iter().flatten() {
+            *counts.tags.entry(tag.clone()).or_default() += 1;
+        }
+        if let Some(priority) = subwhatdo.priority {
+            *counts.priorities.entry(priority).or_default() += 1;
+        }
+        collect_descendant_counts(&subwhatdo, counts);
+    }
+}
+
+/// Summary of the whatdos under a whatdo whose sub-whatdos aren't printed,
+/// like `(+12 more: 8 leaves, tags ui 3 optional 1, priorities P1 2)`
+fn collapsed_summary(wd: &Whatdo) -> String {
+    let mut counts = DescendantCounts::default();
+    collect_descendant_counts(wd, &mut counts);
+
+    let mut summary = format!(
+        "(+{} more: {} {}",
+        counts.descendants,
+        counts.leaves,
+        if counts.leaves == 1 { "leaf" } else { "leaves" }
+    );
+    if !counts.tags.is_empty() {
+        let mut tags: Vec<(String, usize)> = counts.tags.into_iter().collect();
+        tags.sort_by(|(_, a), (_, b)| b.cmp(a));
+        let tags: Vec<String> = tags
+            .into_iter()
+            .map(|(tag, count)| format!("{} {}", tag, count))
+            .collect();
+        summary += &format!(", tags {}", tags.join(" "));
+    }
+    if !counts.priorities.is_empty() {
+        let priorities: Vec<String> = counts
+            .priorities
+            .into_iter()
+            .map(|(priority, count)| format!("P{} {}", priority, count))
+            .collect();
+        summary += &format!(", priorities {}", priorities.join(" "));
+    }
+    summary + ")"
+}
+
+/// Format a YAML value on a single line
+fn format_extra_value(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::Null => String::from("~"),
+        serde_yaml::Value::Bool(b) => b.to_string(),
+        serde_yaml::Value::Number(n) => n.to_string(),
+        serde_yaml::Value::String(s) => s.clone(),
+        serde_yaml::Value::Sequence(items) => format!(
+            "[{}]",
+            items
+                .iter()
+                .map(format_extra_value)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        serde_yaml::Value::Mapping(mapping) => format!(
+            "{{{}}}",
+            mapping
+                .iter()
+                .map(|(k, v)| format!("{}: {}", format_extra_value(k), format_extra_value(v)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        serde_yaml::Value::Tagged(tagged) => {
+            format!("{} {}", tagged.tag, format_extra_value(&tagged.value))
+        }
+    }
 }
 
 impl WhatdoTreeView {
@@ -118,11 +268,17 @@ impl WhatdoTreeView {
         whatdo: &Whatdo,
         unprinted_path: &mut Vec<String>,
         level: usize,
-        ancestor_satisfied_filter: bool,
+        // Level of the highest ancestor that was selected, if any
+        selected_level: Option<usize>,
     ) -> fmt::Result {
         let satisfies_filter = (*self.filter)(whatdo);
         let transitively_satisfies_filter =
-            satisfies_filter || self.transitive && ancestor_satisfied_filter;
+            satisfies_filter || self.transitive && selected_level.is_some();
+        let selected_level = if transitively_satisfies_filter {
+            selected_level.or(Some(level))
+        } else {
+            None
+        };
 
         if whatdo.id != self.root.id {
             if transitively_satisfies_filter {
@@ -148,6 +304,18 @@ impl WhatdoTreeView {
                     "{}",
                     format!("{:>>width$}{}", "", whatdo, width = level - 1)
                 )?;
+                if self.show_extra {
+                    for (k, v) in &whatdo.extra {
+                        writeln!(
+                            f,
+                            "{:>>width$}  {}: {}",
+                            "",
+                            format_extra_value(k).dimmed(),
+                            format_extra_value(v),
+                            width = level - 1
+                        )?;
+                    }
+                }
             } else if transitively_satisfies_filter {
                 writeln!(
                     f,
@@ -159,14 +327,30 @@ impl WhatdoTreeView {
             }
         }
 
-        for wd in whatdo.whatdos() {
-            self.fmt_rec(
+        let mut whatdos = whatdo.whatdos();
+        if let Some(order) = self.sort {
+            whatdos.sort_by(|a, b| order.compare(a, b));
+        }
+        let collapsed = match (self.max_depth, selected_level) {
+            (Some(max_depth), Some(selected_level)) => level - selected_level >= max_depth,
+            _ => false,
+        };
+        if collapsed && !whatdos.is_empty() {
+            writeln!(
                 f,
-                &wd,
-                unprinted_path,
-                level + 1,
-                transitively_satisfies_filter,
+                "{}",
+                format!(
+                    "{:>>width$}{}",
+                    "",
+                    collapsed_summary(whatdo),
+                    width = level
+                )
+                .dimmed()
             )?;
+        } else {
+            for wd in whatdos {
+                self.fmt_rec(f, &wd, unprinted_path, level + 1, selected_level)?;
+            }
         }
 
         // If none of our children cleared the unprinted path,
@@ -181,8 +365,117 @@ impl WhatdoTreeView {
 
 impl<'a> fmt::Display for WhatdoTreeView {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.fmt_rec(f, &self.root, &mut vec![], 0, false)
+        self.fmt_rec(f, &self.root, &mut vec![], 0, None)
+    }
+}
+
+/// Convert a YAML value to JSON. Mapping keys that aren't strings are
+/// formatted as strings, and tags are dropped
+fn yaml_to_json(value: &serde_yaml::Value) -> serde_json::Value {
+    match value {
+        serde_yaml::Value::Null => serde_json::Value::Null,
+        serde_yaml::Value::Bool(b) => serde_json::Value::from(*b),
+        serde_yaml::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                serde_json::Value::from(i)
+            } else if let Some(u) = n.as_u64() {
+                serde_json::Value::from(u)
+            } else {
+                serde_json::Value::from(n.as_f64())
+            }
+        }
+        serde_yaml::Value::String(s) => serde_json::Value::from(s.clone()),
+        serde_yaml::Value::Sequence(items) => items.iter().map(yaml_to_json).collect(),
+        serde_yaml::Value::Mapping(mapping) => serde_json::Value::Object(
+            mapping
+                .iter()
+                .map(|(k, v)| (format_extra_value(k), yaml_to_json(v)))
+                .collect(),
+        ),
+        serde_yaml::Value::Tagged(tagged) => yaml_to_json(&tagged.value),
+    }
+}
+
+fn date_json(date: Option<NaiveDate>) -> serde_json::Value {
+    date.map_or(serde_json::Value::Null, |date| {
+        serde_json::Value::from(date.format(DATE_FORMAT).to_string())
+    })
+}
+
+/// JSON representation of the whatdo and its sub-whatdos. `path` is the IDs
+/// of the whatdo's ancestors, starting from the root
+fn whatdo_json_with_path(wd: &Whatdo, path: &[String]) -> serde_json::Value {
+    let mut subwhatdo_path = path.to_vec();
+    subwhatdo_path.push(wd.id.clone());
+    json!({
+        "id": wd.id,
+        "summary": wd.summary(),
+        "priority": wd.priority,
+        "tags": wd.tags.clone().unwrap_or_default(),
+        "branch_name": wd.branch_name(),
+        "queue": wd.queue.clone().unwrap_or_default(),
+        "blocked_by": wd.blocked_by.clone().unwrap_or_default(),
+        "due": date_json(wd.due),
+        "days_until_due": wd.days_until_due(),
+        "created": date_json(wd.created),
+        "updated": date_json(wd.updated),
+        "extra": yaml_to_json(&serde_yaml::Value::Mapping(wd.extra.clone())),
+        "path": path,
+        "whatdos": wd
+            .whatdos()
+            .iter()
+            .map(|subwhatdo| whatdo_json_with_path(subwhatdo, &subwhatdo_path))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// IDs of the ancestors of the whatdo with the given ID, starting from the root
+fn ancestor_ids(root: &Whatdo, id: &str) -> Option<Vec<String>> {
+    if root.id == id {
+        return Some(Vec::new());
+    }
+
+    root.whatdos().iter().find_map(|wd| {
+        ancestor_ids(wd, id).map(|mut ids| {
+            ids.insert(0, root.id.clone());
+            ids
+        })
+    })
+}
+
+fn collect_ancestor_ids(
+    wd: &Whatdo,
+    ancestors: &mut Vec<String>,
+    result: &mut HashMap<String, Vec<String>>,
+) {
+    result.insert(wd.id.clone(), ancestors.clone());
+    ancestors.push(wd.id.clone());
+    for subwhatdo in wd.whatdos() {
+        collect_ancestor_ids(&subwhatdo, ancestors, result);
     }
+    ancestors.pop();
+}
+
+/// Filter for the whatdos in the given tree that satisfy the query, or all
+/// whatdos if there is no query
+pub fn query_filter(root: &Whatdo, query: Option<Query>) -> Box<dyn Fn(&Whatdo) -> bool> {
+    let query = match query {
+        None => return Box::new(|_| true),
+        Some(query) => query,
+    };
+
+    let mut ancestors = HashMap::new();
+    collect_ancestor_ids(root, &mut Vec::new(), &mut ancestors);
+    Box::new(move |wd| {
+        let wd_ancestors = ancestors.get(&wd.id).map_or(&[][..], |a| a.as_slice());
+        query.matches(wd, wd_ancestors)
+    })
+}
+
+/// JSON representation of the whatdo for scripts, including the path to it
+/// from the root of the given tree and all of its sub-whatdos
+pub fn whatdo_json(root: &Whatdo, wd: &Whatdo) -> serde_json::Value {
+    whatdo_json_with_path(wd, &ancestor_ids(root, &wd.id).unwrap_or_default())
 }
 
 static TAG_RE: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new("^[a-z0-9-_]+$").unwrap());
@@ -219,13 +512,13 @@ fn get_project_name(path: &Path) -> Result<String> {
     }
 }
 
-fn parse_whatdo_map(mapping: serde_yaml::Mapping) -> Result<Vec<Whatdo>> {
+fn parse_whatdo_map(mapping: serde_yaml::Mapping, dir: &Path) -> Result<Vec<Whatdo>> {
     mapping
         .iter()
         .map(|(k, v)| {
             let id = match k {
                 serde_yaml::Value::String(s) => {
-                    if valid_id(&s) {
+                    if valid_id(s) {
                         s
                     } else {
                         return Err(Error::msg(format!("Invalid whatdo ID: {}", s)));
@@ -233,12 +526,55 @@ fn parse_whatdo_map(mapping: serde_yaml::Mapping) -> Result<Vec<Whatdo>> {
                 }
                 _ => return Err(Error::msg("Expected mapping key to be a string")),
             };
-            Ok(parse_whatdo(id, v)?)
+            parse_whatdo(id, v, dir)
+        })
+        .collect()
+}
+
+/// Recursively collect the paths of all YAML files under the given directory,
+/// in sorted order
+fn yaml_files_in_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            result.append(&mut yaml_files_in_dir(&path)?);
+        } else if path.extension().is_some_and(|e| e == "yaml") {
+            result.push(path);
+        }
+    }
+    Ok(result)
+}
+
+/// Parse a directory holding one file per whatdo. The ID of each whatdo is
+/// the path of its file relative to the directory, without the extension
+fn parse_whatdo_dir(dir: &Path) -> Result<Vec<Whatdo>> {
+    yaml_files_in_dir(dir)?
+        .iter()
+        .map(|path| {
+            let relative = path.strip_prefix(dir)?.with_extension("");
+            let id = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if !valid_id(&id) {
+                return Err(Error::msg(format!(
+                    "Invalid whatdo ID: {} (from {})",
+                    id,
+                    path.to_string_lossy()
+                )));
+            }
+            let content: serde_yaml::Value = serde_yaml::from_reader(std::fs::File::open(path)?)?;
+            parse_whatdo(&id, &content, path.parent().unwrap_or(dir))
         })
         .collect()
 }
 
-fn parse_queue_sequence(list: serde_yaml::Sequence) -> Result<Vec<String>> {
+fn parse_id_sequence(list: serde_yaml::Sequence) -> Result<Vec<String>> {
     list.iter()
         .map(|v| {
             let id = match v {
@@ -274,10 +610,76 @@ fn parse_tags_sequence(list: serde_yaml::Sequence) -> Result<Vec<String>> {
         .collect()
 }
 
-fn parse_whatdo(id: &str, data: &serde_yaml::Value) -> Result<Whatdo> {
+/// Keys of a whatdo mapping that are parsed into fields of `Whatdo`. Any
+/// other keys are kept in `Whatdo::extra`
+const WHATDO_KEYS: [&str; 10] = [
+    "summary",
+    "whatdos",
+    "queue",
+    "priority",
+    "tags",
+    "branch_name",
+    "blocked_by",
+    "due",
+    "created",
+    "updated",
+];
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Whatdos due within this many days are ordered before undated whatdos of
+/// the same priority
+const DUE_SOON_DAYS: i64 = 7;
+
+fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+fn parse_date(items: &Mapping, key: &str) -> Result<Option<NaiveDate>> {
+    match items.get(key) {
+        None => Ok(None),
+        Some(serde_yaml::Value::String(s)) => match NaiveDate::parse_from_str(s, DATE_FORMAT) {
+            Ok(date) => Ok(Some(date)),
+            Err(_) => Err(Error::msg(format!(
+                "Expected '{}' to be a date like 2024-01-31, got '{}'",
+                key, s
+            ))),
+        },
+        Some(_) => Err(Error::msg(format!("Expected '{}' to be a date", key))),
+    }
+}
+
+/// Parse the whatdo with the given ID from its YAML data. `dir` is the directory
+/// of the file containing the data, against which included paths are resolved
+fn parse_whatdo(id: &str, data: &serde_yaml::Value, dir: &Path) -> Result<Whatdo> {
     match data {
         serde_yaml::Value::String(s) => Ok(Whatdo::simple(id.to_owned(), Some(s.clone()))),
         serde_yaml::Value::Mapping(items) => {
+            if let Some(include) = items.get("include") {
+                let include = match include {
+                    serde_yaml::Value::String(s) => PathBuf::from(s),
+                    _ => return Err(Error::msg("Expected 'include' to be a string")),
+                };
+                if items.len() > 1 {
+                    return Err(Error::msg(format!(
+                        "Whatdo '{}' has an 'include' and other keys. Move the other keys into the included file",
+                        id
+                    )));
+                }
+                let path = dir.join(&include);
+                let content: serde_yaml::Value =
+                    serde_yaml::from_reader(std::fs::File::open(&path).map_err(|e| {
+                        Error::msg(format!(
+                            "Could not open included file {}: {}",
+                            path.to_string_lossy(),
+                            e
+                        ))
+                    })?)?;
+                let mut whatdo = parse_whatdo(id, &content, path.parent().unwrap_or(dir))?;
+                whatdo.include = Some(include);
+                return Ok(whatdo);
+            }
+
             let summary = match items.get("summary") {
                 None => None,
                 Some(s) => match s {
@@ -285,11 +687,24 @@ fn parse_whatdo(id: &str, data: &serde_yaml::Value) -> Result<Whatdo> {
                     _ => return Err(Error::msg("Expected 'summary' to be a string")),
                 },
             };
-            let whatdos_map = match items.get("whatdos") {
-                None => None,
+            let (whatdos, whatdos_dir) = match items.get("whatdos") {
+                None => (None, None),
                 Some(d) => match d {
-                    serde_yaml::Value::Mapping(d) => Some(d.clone()),
-                    _ => return Err(Error::msg("Expected 'whatdos' to be a mapping")),
+                    serde_yaml::Value::Mapping(d) => {
+                        (Some(parse_whatdo_map(d.clone(), dir)?), None)
+                    }
+                    serde_yaml::Value::String(s) => {
+                        let whatdos_dir = PathBuf::from(s);
+                        (
+                            Some(parse_whatdo_dir(&dir.join(&whatdos_dir))?),
+                            Some(whatdos_dir),
+                        )
+                    }
+                    _ => {
+                        return Err(Error::msg(
+                            "Expected 'whatdos' to be a mapping or a directory path",
+                        ))
+                    }
                 },
             };
             let queue_sequence = match items.get("queue") {
@@ -323,16 +738,35 @@ fn parse_whatdo(id: &str, data: &serde_yaml::Value) -> Result<Whatdo> {
                     _ => return Err(Error::msg("Expected 'branch_name' to be a string")),
                 },
             };
+            let blocked_by_sequence = match items.get("blocked_by") {
+                None => None,
+                Some(d) => match d {
+                    serde_yaml::Value::Sequence(s) => Some(s.clone()),
+                    _ => return Err(Error::msg("Expected 'blocked_by' to be a sequence")),
+                },
+            };
+            let extra: Mapping = items
+                .iter()
+                .filter(|(k, _)| !k.as_str().is_some_and(|k| WHATDO_KEYS.contains(&k)))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
 
             Ok(Whatdo {
                 id: String::from(id),
                 summary: summary.cloned(),
-                whatdos: whatdos_map.map(parse_whatdo_map).transpose()?,
-                queue: queue_sequence.map(parse_queue_sequence).transpose()?,
+                whatdos,
+                queue: queue_sequence.map(parse_id_sequence).transpose()?,
                 tags: tags_sequence.map(parse_tags_sequence).transpose()?,
                 priority,
                 branch_name,
                 simple_format: false,
+                include: None,
+                whatdos_dir,
+                blocked_by: blocked_by_sequence.map(parse_id_sequence).transpose()?,
+                extra,
+                created: parse_date(items, "created")?,
+                updated: parse_date(items, "updated")?,
+                due: parse_date(items, "due")?,
             })
         }
         _ => Err(Error::msg("Whatdo data must be string or mapping")),
@@ -344,28 +778,102 @@ fn parse_file(path: &Path) -> Result<Whatdo> {
     // let parsed: ParsedWhatdo = serde_yaml::from_slice(&file)?;
     let content: serde_yaml::Value = serde_yaml::from_reader(file)?;
     // let yaml_content = parser.load(file)?;
-    let project_name = get_project_name(&path)?;
+    let project_name = get_project_name(path)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    parse_whatdo(&project_name, &content, dir)
+}
+
+/// Return the whatdo file to operate on: the given file if any, otherwise
+/// WHATDO.yaml at the root of the current git repo. Outside of a git repo,
+/// this is the closest WHATDO.yaml in the current directory or its parents
+pub fn get_current_file(file: Option<&Path>) -> Result<PathBuf> {
+    let current_dir = std::env::current_dir()?;
+    if let Some(file) = file {
+        return Ok(current_dir.join(file));
+    }
+
+    if let Some(root) = git::get_root()? {
+        return Ok(root.join("WHATDO.yaml"));
+    }
+
+    Ok(current_dir
+        .ancestors()
+        .map(|dir| dir.join("WHATDO.yaml"))
+        .find(|path| path.exists())
+        .unwrap_or(current_dir.join("WHATDO.yaml")))
+}
+
+/// Whether the whatdo file is in the current git repo. If not, nothing is
+/// committed and the active whatdo is tracked in a state file instead of by
+/// the current branch
+fn uses_git(file: &Path) -> Result<bool> {
+    Ok(git::get_root()?.is_some_and(|root| file.starts_with(root)))
+}
 
-    return parse_whatdo(&project_name, &content);
+/// The file storing the ID of the active whatdo when not using git
+fn state_file(file: &Path) -> PathBuf {
+    let file_name = file
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or(String::from("WHATDO.yaml"));
+    file.parent()
+        .unwrap_or(Path::new("."))
+        .join(".whatdo")
+        .join(format!("{}.active", file_name))
 }
 
-pub fn get_current_file() -> Result<PathBuf> {
-    let root: PathBuf = git::get_root()?;
-    Ok(root.join("WHATDO.yaml"))
+fn set_active_id(file: &Path, id: Option<&str>) -> Result<()> {
+    let path = state_file(file);
+    match id {
+        Some(id) => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, id)?;
+        }
+        None => {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+    }
+    Ok(())
 }
 
-fn read_current_file() -> Result<Whatdo> {
-    return parse_file(&get_current_file()?);
+fn active_id(file: &Path) -> Result<Option<String>> {
+    let path = state_file(file);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let id = std::fs::read_to_string(path)?.trim().to_owned();
+    Ok(Some(id).filter(|id| !id.is_empty()))
 }
 
+/// Serialize the whatdo as a mapping key and value. If the whatdo's body is
+/// stored in a separate file, the value only refers to that file
 fn serialize_whatdo(whatdo: &Whatdo) -> (serde_yaml::Value, serde_yaml::Value) {
+    let key = serde_yaml::Value::String(whatdo.id.clone());
+    if let Some(include) = &whatdo.include {
+        let mut mapping = serde_yaml::Mapping::new();
+        mapping.insert(
+            serde_yaml::Value::String(String::from("include")),
+            serde_yaml::Value::String(include.to_string_lossy().into_owned()),
+        );
+        return (key, serde_yaml::Value::Mapping(mapping));
+    }
+
+    (key, serialize_whatdo_body(whatdo))
+}
+
+fn serialize_whatdo_body(whatdo: &Whatdo) -> serde_yaml::Value {
     if whatdo.simple_format() {
-        let summary_value = if let Some(summary) = whatdo.summary.clone() {
+        return if let Some(summary) = whatdo.summary.clone() {
             serde_yaml::Value::String(summary)
         } else {
             serde_yaml::Value::Mapping(Mapping::new())
         };
-        return (serde_yaml::Value::String(whatdo.id.clone()), summary_value);
     }
 
     let mut mapping = serde_yaml::Mapping::new();
@@ -412,10 +920,44 @@ fn serialize_whatdo(whatdo: &Whatdo) -> (serde_yaml::Value, serde_yaml::Value) {
         );
     }
 
-    if let Some(whatdos) = whatdo.whatdos.clone() {
+    if let Some(blocked_by) = whatdo.blocked_by.clone() {
+        mapping.insert(
+            serde_yaml::Value::String(String::from("blocked_by")),
+            serde_yaml::Value::Sequence(
+                blocked_by
+                    .into_iter()
+                    .map(serde_yaml::Value::String)
+                    .collect(),
+            ),
+        );
+    }
+
+    for (key, date) in [
+        ("due", whatdo.due),
+        ("created", whatdo.created),
+        ("updated", whatdo.updated),
+    ] {
+        if let Some(date) = date {
+            mapping.insert(
+                serde_yaml::Value::from(key),
+                serde_yaml::Value::String(date.format(DATE_FORMAT).to_string()),
+            );
+        }
+    }
+
+    for (k, v) in &whatdo.extra {
+        mapping.insert(k.clone(), v.clone());
+    }
+
+    if let Some(whatdos_dir) = &whatdo.whatdos_dir {
+        mapping.insert(
+            serde_yaml::Value::String(String::from("whatdos")),
+            serde_yaml::Value::String(whatdos_dir.to_string_lossy().into_owned()),
+        );
+    } else if let Some(whatdos) = whatdo.whatdos.clone() {
         let mut whatdo_mapping = serde_yaml::Mapping::new();
         for subwhatdo in &whatdos {
-            let (k, v) = serialize_whatdo(&subwhatdo);
+            let (k, v) = serialize_whatdo(subwhatdo);
             whatdo_mapping.insert(k, v);
         }
 
@@ -425,20 +967,345 @@ fn serialize_whatdo(whatdo: &Whatdo) -> (serde_yaml::Value, serde_yaml::Value) {
         );
     }
 
-    return (
-        serde_yaml::Value::String(whatdo.id.clone()),
-        serde_yaml::Value::Mapping(mapping),
-    );
+    serde_yaml::Value::Mapping(mapping)
+}
+
+/// Whether the `whatdo.offline` git config option is set, so that pushes are
+/// always queued instead of run
+fn offline() -> Result<bool> {
+    Ok(git::config_bool("whatdo.offline")?.unwrap_or(false))
+}
+
+/// The remote named by the config option, which must exist. If the option isn't
+/// set, `origin`, or the only remote. None if there is no remote, or if there are
+/// several and none of them is `origin`
+fn pick_remote(
+    key: &str,
+    configured: Option<String>,
+    remotes: &[String],
+) -> Result<Option<String>> {
+    if let Some(remote) = configured {
+        if !remotes.contains(&remote) {
+            return Err(Error::msg(format!(
+                "{} is set to '{}', but there is no remote with that name",
+                key, remote
+            )));
+        }
+        return Ok(Some(remote));
+    }
+
+    if remotes.iter().any(|remote| remote == "origin") {
+        return Ok(Some("origin".to_owned()));
+    }
+    match remotes {
+        [remote] => Ok(Some(remote.clone())),
+        _ => Ok(None),
+    }
+}
+
+/// The remote that branches are pushed to, from `whatdo.pushRemote`
+fn push_remote() -> Result<Option<String>> {
+    let key = "whatdo.pushRemote";
+    pick_remote(key, git::config_string(key)?, &git::remotes()?)
+}
+
+/// The remote whose default branch whatdos are merged into, from
+/// `whatdo.baseRemote`. Defaults to the push remote
+fn base_remote() -> Result<Option<String>> {
+    let key = "whatdo.baseRemote";
+    match git::config_string(key)? {
+        Some(remote) => pick_remote(key, Some(remote), &git::remotes()?),
+        None => push_remote(),
+    }
+}
+
+fn push_branch(branch: &str) -> Result<()> {
+    match push_remote()? {
+        Some(remote) => Ok(git::push_branch(&remote, branch)?),
+        None if git::remotes()?.is_empty() => Ok(()),
+        None => Err(Error::msg(
+            "Not sure which remote to push to. Set one with `git config whatdo.pushRemote <remote>`",
+        )),
+    }
+}
+
+fn pending_pushes_file() -> Result<PathBuf> {
+    Ok(git::git_dir()?.join("whatdo").join("pending-pushes"))
+}
+
+fn read_pending_pushes(path: &Path) -> Result<Vec<String>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_owned())
+        .collect())
+}
+
+fn write_pending_pushes(path: &Path, branches: &[String]) -> Result<()> {
+    if branches.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, branches.join("\n") + "\n")?;
+    Ok(())
+}
+
+/// Add the branch to the queue in the file, unless it's already queued
+fn queue_push(path: &Path, branch: &str) -> Result<()> {
+    let mut pending = read_pending_pushes(path)?;
+    if !pending.iter().any(|b| b == branch) {
+        pending.push(branch.to_owned());
+        write_pending_pushes(path, &pending)?;
+    }
+    Ok(())
+}
+
+/// Push each branch queued in the file with `push`, which returns false if it
+/// skipped the branch. The queue is written after every push, so that a failure
+/// leaves the failed branch and the ones after it queued. Returns the branches
+/// pushed
+fn flush_pending_pushes(
+    path: &Path,
+    push: &mut impl FnMut(&str) -> Result<bool>,
+) -> Result<Vec<String>> {
+    let mut pending = read_pending_pushes(path)?;
+    let mut pushed = Vec::new();
+    while let Some(branch) = pending.first().cloned() {
+        if push(&branch)? {
+            pushed.push(branch);
+        }
+        pending.remove(0);
+        write_pending_pushes(path, &pending)?;
+    }
+    Ok(pushed)
+}
+
+/// Push the branch, or queue it to be pushed by `wd sync` if `push` is false or
+/// when offline
+fn push_or_queue(branch: &str, push: bool) -> Result<()> {
+    if push && !offline()? {
+        return push_branch(branch);
+    }
+    queue_push(&pending_pushes_file()?, branch)
+}
+
+/// The branches waiting to be pushed by `wd sync`, oldest first
+pub fn pending_pushes(file: &Path) -> Result<Vec<String>> {
+    if !uses_git(file)? {
+        return Ok(Vec::new());
+    }
+    read_pending_pushes(&pending_pushes_file()?)
+}
+
+/// Push the branches that were queued while offline. Branches that have since
+/// been deleted are skipped. Returns the branches pushed
+pub fn sync(file: &Path) -> Result<Vec<String>> {
+    if !uses_git(file)? {
+        return Ok(Vec::new());
+    }
+    flush_pending_pushes(&pending_pushes_file()?, &mut |branch| {
+        if !git::branch_exists(branch)? {
+            return Ok(false);
+        }
+        push_branch(branch)?;
+        Ok(true)
+    })
+}
+
+/// The files written or removed by a change, along with what they held before
+/// so that the change can be rolled back
+#[derive(Default)]
+struct Touched {
+    originals: Vec<(PathBuf, Option<Vec<u8>>)>,
+}
+
+impl Touched {
+    /// Remember what the file holds before it's first written or removed
+    fn record(&mut self, path: &Path) -> Result<()> {
+        if self.originals.iter().any(|(p, _)| p == path) {
+            return Ok(());
+        }
+        let original = if path.exists() {
+            Some(std::fs::read(path)?)
+        } else {
+            None
+        };
+        self.originals.push((path.to_owned(), original));
+        Ok(())
+    }
+
+    fn paths(&self) -> Vec<PathBuf> {
+        self.originals.iter().map(|(p, _)| p.clone()).collect()
+    }
+
+    /// Put every file back the way it was before the change
+    fn roll_back(&self) -> Result<()> {
+        for (path, original) in self.originals.iter().rev() {
+            match original {
+                Some(content) => std::fs::write(path, content)?,
+                None if path.exists() => std::fs::remove_file(path)?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Roll back the change if the git step failed
+    fn roll_back_on_error<T>(&self, result: git::Result<T>) -> Result<T> {
+        result.or_else(|e| {
+            self.roll_back()?;
+            // Best effort, since the failed step is the error worth reporting
+            let _ = git::unstage(self.paths());
+            Err(Error::msg(format!(
+                "{}\nThe change to the whatdo file was rolled back",
+                e
+            )))
+        })
+    }
+
+    /// Commit the files, rolling back the change if that fails, then push it.
+    /// Does nothing if no files were changed
+    fn commit(&self, message: &str, push: bool) -> Result<()> {
+        if self.originals.is_empty() {
+            return Ok(());
+        }
+        self.roll_back_on_error(git::commit(self.paths(), message))?;
+        push_or_queue(&git::current_branch()?, push).map_err(|e| {
+            Error::msg(format!(
+                "{}\nThe change to the whatdo file was committed but not pushed",
+                e
+            ))
+        })
+    }
+}
+
+/// Write the YAML value to the given path. If the file already exists, only
+/// the parts of it that changed are rewritten so that comments and formatting
+/// are preserved
+fn write_yaml(path: &Path, value: &serde_yaml::Value, touched: &mut Touched) -> Result<()> {
+    let content = if path.exists() {
+        let existing = std::fs::read_to_string(path)?;
+        let updated = yaml_edit::update(&existing, value)?;
+        if updated == existing {
+            return Ok(());
+        }
+        updated
+    } else {
+        serde_yaml::to_string(value)?
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    touched.record(path)?;
+    std::fs::write(path, content)?;
+    Ok(())
 }
 
-fn write_to_file(whatdo: &Whatdo) -> Result<()> {
-    let path = get_current_file()?;
-    let serialized = serialize_whatdo(whatdo);
-    let file = std::fs::File::create(path)?;
-    serde_yaml::to_writer(file, &serialized.1)?;
+/// Write the whatdo's body to the given path, then write each of its
+/// included subtrees back to the file it came from
+fn write_whatdo_file(whatdo: &Whatdo, path: &Path, touched: &mut Touched) -> Result<()> {
+    write_yaml(path, &serialize_whatdo_body(whatdo), touched)?;
+    write_included_files(whatdo, path.parent().unwrap_or(Path::new(".")), touched)
+}
+
+/// Write the subtrees of the given whatdo that are stored outside the file
+/// containing it. `dir` is the directory of that file
+fn write_included_files(whatdo: &Whatdo, dir: &Path, touched: &mut Touched) -> Result<()> {
+    match &whatdo.whatdos_dir {
+        Some(whatdos_dir) => {
+            let whatdos_dir = dir.join(whatdos_dir);
+            let mut expected = HashSet::new();
+            for wd in whatdo.whatdos() {
+                let path = whatdos_dir.join(format!("{}.yaml", wd.id));
+                let path_dir = path.parent().unwrap_or(&whatdos_dir).to_owned();
+                write_yaml(&path, &serialize_whatdo(&wd).1, touched)?;
+                match &wd.include {
+                    Some(include) => write_whatdo_file(&wd, &path_dir.join(include), touched)?,
+                    None => write_included_files(&wd, &path_dir, touched)?,
+                }
+                expected.insert(path);
+            }
+
+            // Files of whatdos that have since been removed
+            if whatdos_dir.exists() {
+                for path in yaml_files_in_dir(&whatdos_dir)? {
+                    if !expected.contains(&path) {
+                        touched.record(&path)?;
+                        std::fs::remove_file(&path)?;
+                    }
+                }
+            }
+        }
+        None => {
+            for wd in whatdo.whatdos() {
+                match &wd.include {
+                    Some(include) => write_whatdo_file(&wd, &dir.join(include), touched)?,
+                    None => write_included_files(&wd, dir, touched)?,
+                }
+            }
+        }
+    }
     Ok(())
 }
 
+/// Add the files that the whatdo's subtrees are included from, laid out the
+/// same way as `write_included_files` writes them. `dir` is the directory of
+/// the file containing the whatdo
+fn collect_included_files(whatdo: &Whatdo, dir: &Path, files: &mut HashSet<PathBuf>) {
+    for wd in whatdo.whatdos() {
+        let wd_dir = match &whatdo.whatdos_dir {
+            Some(whatdos_dir) => {
+                let whatdos_dir = dir.join(whatdos_dir);
+                let path = whatdos_dir.join(format!("{}.yaml", wd.id));
+                path.parent().unwrap_or(&whatdos_dir).to_owned()
+            }
+            None => dir.to_owned(),
+        };
+        match &wd.include {
+            Some(include) => {
+                let path = wd_dir.join(include);
+                collect_included_files(&wd, path.parent().unwrap_or(&wd_dir), files);
+                files.insert(path);
+            }
+            None => collect_included_files(&wd, &wd_dir, files),
+        }
+    }
+}
+
+/// Write the whatdo tree to the given file and any files it includes.
+/// Returns the files written or removed
+fn write_to_file(file: &Path, whatdo: &Whatdo) -> Result<Touched> {
+    let dir = file.parent().unwrap_or(Path::new("."));
+    let mut previous = HashSet::new();
+    if file.exists() {
+        collect_included_files(&parse_file(file)?, dir, &mut previous);
+    }
+
+    let mut touched = Touched::default();
+    write_whatdo_file(whatdo, file, &mut touched)?;
+
+    // Included files of whatdos that have since been removed
+    let mut current = HashSet::new();
+    collect_included_files(whatdo, dir, &mut current);
+    for path in previous.difference(&current) {
+        if path.exists() {
+            touched.record(path)?;
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(touched)
+}
+
 fn find_whatdo_and_parent<'a, P: Fn(&Whatdo) -> bool>(
     root: &'a Whatdo,
     pred: &P,
@@ -517,6 +1384,36 @@ fn find_ancestor_with_branch(root: &Whatdo, id: &str) -> Result<Option<Whatdo>>
     }
 }
 
+/// The branch that the branch of the whatdo with the given ID is merged into
+/// when it's finished: the branch of its closest ancestor with one, or the
+/// default branch
+fn parent_branch(root: &Whatdo, id: &str) -> Result<String> {
+    let ancestor_branch = find_ancestor_with_branch(root, id)?.and_then(|p| {
+        if p.id == root.id {
+            root.branch_name.clone()
+        } else {
+            Some(p.branch_name().to_owned())
+        }
+    });
+    match ancestor_branch {
+        Some(branch) => Ok(branch),
+        None => default_branch(),
+    }
+}
+
+/// The branch that top-level whatdos are merged into: `whatdo.defaultBranch`,
+/// or the repo's default branch
+fn default_branch() -> Result<String> {
+    if let Some(branch) = git::config_string("whatdo.defaultBranch")? {
+        return Ok(branch);
+    }
+    git::default_branch_name(base_remote()?.as_deref())?.ok_or_else(|| {
+        Error::msg(
+            "Couldn't figure out the default branch. Set it with `git config whatdo.defaultBranch <branch>`",
+        )
+    })
+}
+
 /// Return all whatdos descedent from the given whatdo in the order
 /// defined by the prioritization algorithm.
 /// Ignore any whatdos (or whatdo trees) for which filter(wd) returns false
@@ -524,6 +1421,7 @@ fn find_ancestor_with_branch(root: &Whatdo, id: &str) -> Result<Option<Whatdo>>
 fn sort_whatdos<F: Fn(&Whatdo) -> bool>(
     wd: &Whatdo,
     filter: &F,
+    order: SortOrder,
     visited: &mut HashSet<String>,
     ancestor_satisfies_filter: bool,
 ) -> Vec<Whatdo> {
@@ -545,25 +1443,20 @@ fn sort_whatdos<F: Fn(&Whatdo) -> bool>(
                 }
             };
 
-            let mut other = sort_whatdos(&queue_wd, filter, visited, satisfies_filter);
+            let mut other = sort_whatdos(&queue_wd, filter, order, visited, satisfies_filter);
             result.append(&mut other);
             visited.insert(id.clone());
         }
     }
 
     if let Some(mut whatdos) = wd.whatdos.clone().filter(|wd| wd.len() > 0) {
-        whatdos.sort_by(|a, b| match (a.priority, b.priority) {
-            (Some(pa), Some(pb)) => pa.cmp(&pb),
-            (None, Some(_)) => std::cmp::Ordering::Greater,
-            (Some(_), None) => std::cmp::Ordering::Less,
-            (None, None) => std::cmp::Ordering::Equal,
-        });
+        whatdos.sort_by(|a, b| order.compare(a, b));
         for subwhatdo in whatdos {
             if visited.contains(&subwhatdo.id) {
                 continue;
             }
 
-            let mut other = sort_whatdos(&subwhatdo, filter, visited, satisfies_filter);
+            let mut other = sort_whatdos(&subwhatdo, filter, order, visited, satisfies_filter);
             result.append(&mut other);
             visited.insert(subwhatdo.id.clone());
         }
@@ -577,17 +1470,136 @@ fn sort_whatdos<F: Fn(&Whatdo) -> bool>(
     return result;
 }
 
+/// IDs of the leaf whatdos under the given whatdo, or the whatdo itself if
+/// it is a leaf
+fn leaf_ids(wd: &Whatdo) -> Vec<String> {
+    match wd.whatdos.as_ref().filter(|wds| !wds.is_empty()) {
+        None => vec![wd.id.clone()],
+        Some(wds) => wds.iter().flat_map(leaf_ids).collect(),
+    }
+}
+
+/// Map the ID of each whatdo to the IDs of the whatdos blocking it, which
+/// includes those blocking any of its ancestors
+fn collect_blockers(
+    wd: &Whatdo,
+    ancestor_blockers: &[String],
+    blockers: &mut HashMap<String, Vec<String>>,
+) {
+    let mut wd_blockers = ancestor_blockers.to_vec();
+    wd_blockers.extend(wd.blocked_by.clone().unwrap_or_default());
+    for subwhatdo in wd.whatdos() {
+        collect_blockers(&subwhatdo, &wd_blockers, blockers);
+    }
+    blockers.insert(wd.id.clone(), wd_blockers);
+}
+
+/// Find a cycle in the graph of whatdos waiting on each other, where a whatdo
+/// waits on its blockers and on its sub-whatdos
+fn find_blocking_cycle<'a>(
+    id: &'a str,
+    edges: &'a HashMap<String, Vec<String>>,
+    path: &mut Vec<&'a str>,
+    done: &mut HashSet<&'a str>,
+) -> Option<Vec<String>> {
+    if let Some(start) = path.iter().position(|p| *p == id) {
+        let mut cycle: Vec<String> = path[start..].iter().map(|p| p.to_string()).collect();
+        cycle.push(id.to_owned());
+        return Some(cycle);
+    }
+    if done.contains(id) {
+        return None;
+    }
+
+    path.push(id);
+    for next in edges.get(id).into_iter().flatten() {
+        if let Some(cycle) = find_blocking_cycle(next, edges, path, done) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    done.insert(id);
+    None
+}
+
+/// Reorder sorted whatdos so that no whatdo comes before the whatdos
+/// blocking it, otherwise keeping the given order. Whatdos whose blockers
+/// are not resolved by the end of the list are left out
+fn order_by_blockers(root: &Whatdo, sorted: Vec<Whatdo>) -> Result<Vec<Whatdo>> {
+    let mut blockers = HashMap::new();
+    collect_blockers(root, &[], &mut blockers);
+
+    // Blockers that are no longer in the tree are resolved
+    let mut leaves_by_blocker: HashMap<String, Vec<String>> = HashMap::new();
+    for id in blockers.values().flatten() {
+        if let Some(blocker) = find_whatdo(root, id) {
+            leaves_by_blocker.insert(id.clone(), leaf_ids(&blocker));
+        }
+    }
+
+    let mut edges: HashMap<String, Vec<String>> = HashMap::new();
+    for (id, wd_blockers) in &blockers {
+        let wd = find_whatdo(root, id).unwrap();
+        edges.insert(
+            id.clone(),
+            wd_blockers
+                .iter()
+                .filter(|b| leaves_by_blocker.contains_key(*b))
+                .cloned()
+                .chain(wd.whatdos().into_iter().map(|sub| sub.id))
+                .collect(),
+        );
+    }
+    let mut ids: Vec<&String> = blockers.keys().collect();
+    ids.sort();
+    let mut done = HashSet::new();
+    for id in ids {
+        if let Some(cycle) = find_blocking_cycle(id, &edges, &mut vec![], &mut done) {
+            return Err(Error::msg(format!(
+                "Whatdos are blocking each other in a cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+    }
+
+    let mut remaining = sorted;
+    let mut resolved: HashSet<String> = HashSet::new();
+    let mut result = Vec::new();
+    loop {
+        let unblocked = remaining.iter().position(|wd| {
+            blockers.get(&wd.id).into_iter().flatten().all(|b| {
+                leaves_by_blocker
+                    .get(b)
+                    .is_none_or(|leaves| leaves.iter().all(|l| resolved.contains(l)))
+            })
+        });
+        match unblocked {
+            None => break,
+            Some(i) => {
+                let wd = remaining.remove(i);
+                resolved.insert(wd.id.clone());
+                result.push(wd);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn add(
+    file: &Path,
     id: &str,
     tags: Vec<String>,
     summary: Option<&str>,
     priority: Option<i64>,
     branch_name: Option<String>,
     parent_id: Option<String>,
+    due: Option<NaiveDate>,
     commit: bool,
+    push: bool,
 ) -> Result<(Whatdo, Option<Whatdo>)> {
-    let current_file = get_current_file()?;
-    let mut whatdo = parse_file(&current_file)?;
+    let mut whatdo = parse_file(file)?;
 
     match find_whatdo_and_parent(&whatdo, &|wd| wd.id == id) {
         Some(_) => {
@@ -607,9 +1619,8 @@ pub fn add(
     let new_whatdo = Whatdo {
         id: validate_id(id)?,
         summary: summary.map(|s| s.to_owned()),
-        simple_format: false,
-        queue: None,
-        whatdos: None,
+        created: Some(today()),
+        due,
         tags: if tags.len() > 0 {
             Some(validated_tags)
         } else {
@@ -617,6 +1628,7 @@ pub fn add(
         },
         priority,
         branch_name,
+        ..Default::default()
     };
 
     match find_whatdo_and_parent(&whatdo, &|wd| new_whatdo.branch_name() == wd.branch_name()) {
@@ -629,19 +1641,16 @@ pub fn add(
         None => {}
     }
 
-    if git::branch_exists(new_whatdo.branch_name())? {
-        return Err(Error::msg(format!("Branch with name '{}' already exists", new_whatdo.branch_name())));
+    if uses_git(file)? && git::branch_exists(new_whatdo.branch_name())? {
+        return Err(Error::msg(format!(
+            "Branch with name '{}' already exists",
+            new_whatdo.branch_name()
+        )));
     }
 
     let parent = {
         let parent_wd = if let Some(parent_id) = &parent_id {
-            let normalized_parent_id = match parent_id.as_str() {
-                "@" => match current()? {
-                    None => return Err(Error::msg("No current whatdo to add to")),
-                    Some(wd) => wd.id,
-                },
-                _ => parent_id.clone(),
-            };
+            let normalized_parent_id = resolve_id(file, parent_id)?;
             match find_whatdo_mut(&mut whatdo, &|wd| &wd.id == &normalized_parent_id) {
                 Some(wd) => wd,
                 None => return Err(Error::msg("Parent not found")),
@@ -655,202 +1664,1481 @@ pub fn add(
         parent_wd.whatdos.as_mut().unwrap().push(new_whatdo.clone());
         parent_id.map(|_| parent_wd).cloned()
     };
-    write_to_file(&mut whatdo)?;
+    let touched = write_to_file(file, &whatdo)?;
 
-    if commit {
-        git::commit([current_file], &format!("Add '{}' to whatdos", id), true)?;
+    if commit && uses_git(file)? {
+        touched.commit(&format!("Add '{}' to whatdos", id), push)?;
     }
 
     Ok((new_whatdo, parent))
 }
 
-pub enum NextAmount {
-    All,
-    AtMost(usize),
-}
+static CHECKLIST_RE: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new(r"^(\s*)[-*+] \[([ xX])\]\s+(.+)$").unwrap());
 
-pub fn next(amount: NextAmount, tags: Vec<String>, priorities: Vec<i64>) -> Result<Vec<Whatdo>> {
-    let root = read_current_file()?;
-    let current_wd = current()?;
-    let mut visited = HashSet::new();
-    if let Some(current_id) = current_wd.clone().map(|c| c.id) {
-        visited.insert(current_id);
+/// Make a slug-style ID out of a freetext summary
+fn id_from_summary(summary: &str) -> Result<String> {
+    let mut id = String::new();
+    for c in summary.chars() {
+        if c.is_ascii_alphanumeric() {
+            id.push(c.to_ascii_lowercase());
+        } else if !id.is_empty() && !id.ends_with('-') {
+            id.push('-');
+        }
+    }
+    id.truncate(48);
+    let id = id.trim_end_matches('-');
+    if id.is_empty() {
+        return Err(Error::msg(format!("Cannot make an ID from '{}'", summary)));
     }
+    Ok(id.to_owned())
+}
 
-    let filter = |wd: &Whatdo| {
-        (tags.len() == 0
-            || wd
-                .tags
-                .as_ref()
-                .map(|ts| tags.iter().find(|t| ts.contains(t)))
-                .is_some())
-            && (priorities.len() == 0
-                || (wd.priority.is_some() && priorities.contains(&wd.priority.unwrap())))
-    };
+/// Parse a markdown checklist, nesting items by indentation. Checked items and
+/// the items under them are skipped
+fn parse_checklist(text: &str) -> Result<Vec<Whatdo>> {
+    // Indentation and whatdo of each item on the path to the last item
+    let mut stack: Vec<(usize, Whatdo)> = Vec::new();
+    let mut result = Vec::new();
+    let mut skip_deeper_than: Option<usize> = None;
 
-    let mut current_sorted = if let Some(wd) = current_wd.clone() {
-        sort_whatdos(&wd, &filter, &mut visited, false)
-    } else {
-        vec![]
+    let pop = |stack: &mut Vec<(usize, Whatdo)>, result: &mut Vec<Whatdo>| {
+        let (_, wd) = stack.pop().unwrap();
+        match stack.last_mut() {
+            Some((_, parent)) => parent.whatdos.get_or_insert_with(Vec::new).push(wd),
+            None => result.push(wd),
+        }
     };
 
-    let mut rest_sorted = sort_whatdos(&root, &filter, &mut visited, false);
-    current_sorted.append(&mut rest_sorted);
-    match amount {
-        NextAmount::All => Ok(current_sorted),
-        NextAmount::AtMost(n) => Ok(current_sorted.into_iter().take(n as usize).collect()),
+    for line in text.lines() {
+        let captures = match CHECKLIST_RE.captures(line) {
+            Some(captures) => captures,
+            None => continue,
+        };
+        let indent = captures[1].len();
+        if skip_deeper_than.is_some_and(|skipped| indent > skipped) {
+            continue;
+        }
+        skip_deeper_than = None;
+        while stack.last().is_some_and(|(i, _)| *i >= indent) {
+            pop(&mut stack, &mut result);
+        }
+        if &captures[2] != " " {
+            skip_deeper_than = Some(indent);
+            continue;
+        }
+
+        let summary = captures[3].trim();
+        stack.push((
+            indent,
+            Whatdo::simple(id_from_summary(summary)?, Some(summary.to_owned())),
+        ));
+    }
+    while !stack.is_empty() {
+        pop(&mut stack, &mut result);
+    }
+    Ok(result)
+}
+
+/// Parse whatdos to add in bulk. The text can be a markdown checklist, a YAML
+/// mapping like the `whatdos` of a whatdo file, or `id: summary` lines
+pub fn parse_whatdo_list(text: &str, dir: &Path) -> Result<Vec<Whatdo>> {
+    if text.lines().any(|line| CHECKLIST_RE.is_match(line)) {
+        return parse_checklist(text);
+    }
+
+    if let Ok(serde_yaml::Value::Mapping(mapping)) = serde_yaml::from_str(text) {
+        return parse_whatdo_map(mapping, dir);
     }
+
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (id, summary) = match line.split_once(':') {
+                Some((id, summary)) => (id.trim(), Some(summary.trim())),
+                None => (line, None),
+            };
+            Ok(Whatdo::simple(
+                validate_id(id).map_err(|e| Error::msg(format!("{} in '{}'", e, line)))?,
+                summary.filter(|s| !s.is_empty()).map(|s| s.to_owned()),
+            ))
+        })
+        .collect()
 }
 
-pub fn start(wd: &Whatdo) -> Result<()> {
-    git::checkout_new_branch(wd.branch_name(), true)
+fn visit_whatdos(wd: &Whatdo, f: &mut impl FnMut(&Whatdo)) {
+    f(wd);
+    for subwhatdo in wd.whatdos() {
+        visit_whatdos(&subwhatdo, f);
+    }
 }
 
-pub fn get(id: &str) -> Result<Option<Whatdo>> {
-    let whatdo = read_current_file()?;
-    Ok(find_whatdo(&whatdo, id))
+/// Stamp the whatdo and its sub-whatdos as created today
+fn stamp_created(wd: &mut Whatdo) {
+    wd.simple_format = false;
+    wd.created.get_or_insert_with(today);
+    for subwhatdo in wd.whatdos.iter_mut().flatten() {
+        stamp_created(subwhatdo);
+    }
 }
 
-pub fn root() -> Result<Option<Whatdo>> {
-    let current_file = get_current_file()?;
-    if !current_file.exists() {
-        return Ok(None);
+/// Add whatdos and their sub-whatdos under the parent, or the root, in one
+/// change. Every problem with the new whatdos is reported at once, and nothing
+/// is added if there are any
+pub fn add_many(
+    file: &Path,
+    new_whatdos: Vec<Whatdo>,
+    parent_id: Option<String>,
+    commit: bool,
+    push: bool,
+) -> Result<(Vec<Whatdo>, Option<Whatdo>)> {
+    let mut whatdo = parse_file(file)?;
+    if new_whatdos.is_empty() {
+        return Err(Error::msg("No whatdos to add"));
+    }
+
+    let mut ids = HashSet::new();
+    let mut branch_names = HashSet::new();
+    visit_whatdos(&whatdo, &mut |wd| {
+        ids.insert(wd.id.clone());
+        branch_names.insert(wd.branch_name().clone());
+    });
+
+    let mut new_ids = HashSet::new();
+    let mut new_branch_names = HashSet::new();
+    let mut problems = Vec::new();
+    let mut new = Vec::new();
+    for wd in &new_whatdos {
+        visit_whatdos(wd, &mut |wd| new.push(wd.clone()));
+    }
+    for wd in &new {
+        if let Err(e) = validate_id(&wd.id) {
+            problems.push(format!("'{}': {}", wd.id, e));
+        }
+        for tag in wd.tags.iter().flatten() {
+            if let Err(e) = validate_tag(tag) {
+                problems.push(format!("'{}': {}", wd.id, e));
+            }
+        }
+        if ids.contains(&wd.id) {
+            problems.push(format!("Whatdo with ID '{}' already exists", wd.id));
+        } else if branch_names.contains(wd.branch_name()) {
+            problems.push(format!(
+                "Whatdo with branch name '{}' already exists",
+                wd.branch_name()
+            ));
+        } else if !new_ids.insert(&wd.id) || !new_branch_names.insert(wd.branch_name()) {
+            problems.push(format!("'{}' is in the list more than once", wd.id));
+        } else if uses_git(file)? && git::branch_exists(wd.branch_name())? {
+            problems.push(format!(
+                "Branch with name '{}' already exists",
+                wd.branch_name()
+            ));
+        }
+    }
+    if !problems.is_empty() {
+        return Err(Error::msg(format!(
+            "Cannot add the whatdos:\n{}",
+            problems.join("\n")
+        )));
+    }
+
+    let mut new_whatdos = new_whatdos;
+    for wd in &mut new_whatdos {
+        stamp_created(wd);
+    }
+
+    let parent = {
+        let parent_wd = if let Some(parent_id) = &parent_id {
+            let normalized_parent_id = resolve_id(file, parent_id)?;
+            match find_whatdo_mut(&mut whatdo, &|wd| wd.id == normalized_parent_id) {
+                Some(wd) => wd,
+                None => return Err(Error::msg("Parent not found")),
+            }
+        } else {
+            &mut whatdo
+        };
+        parent_wd
+            .whatdos
+            .get_or_insert_with(Vec::new)
+            .extend(new_whatdos.iter().cloned());
+        parent_id.map(|_| parent_wd).cloned()
+    };
+    let touched = write_to_file(file, &whatdo)?;
+
+    if commit && uses_git(file)? {
+        touched.commit(&format!("Add {} whatdos", new.len()), push)?;
     }
 
-    Ok(Some(read_current_file()?))
+    Ok((new_whatdos, parent))
 }
 
-pub fn current() -> Result<Option<Whatdo>> {
-    let whatdo = read_current_file()?;
-    let current_branch = git::current_branch()?;
-    if let Some((wd, _)) =
-        find_whatdo_and_parent(&whatdo, &|wd| wd.branch_name() == &current_branch)
-    {
-        return Ok(Some(wd.clone()));
+/// Changes to make to an existing whatdo. Fields that are None are left as-is
+#[derive(Default)]
+pub struct WhatdoEdit {
+    pub summary: Option<String>,
+    pub priority: Option<i64>,
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    pub branch_name: Option<String>,
+    pub due: Option<NaiveDate>,
+    pub remove_due: bool,
+}
+
+/// Apply the changes to the whatdo, returning a description of each change made
+fn apply_edit(wd: &mut Whatdo, changes: &WhatdoEdit) -> Result<Vec<String>> {
+    let mut descriptions = Vec::new();
+
+    if let Some(summary) = &changes.summary {
+        if wd.summary.as_ref() != Some(summary) {
+            wd.summary = Some(summary.clone());
+            descriptions.push(String::from("updated summary"));
+        }
     }
-    Ok(None)
+
+    if let Some(priority) = changes.priority {
+        if wd.priority != Some(priority) {
+            wd.priority = Some(priority);
+            descriptions.push(format!("set priority to {}", priority));
+        }
+    }
+
+    let mut tags = wd.tags.clone().unwrap_or_default();
+    let mut tags_changed = false;
+    for tag in &changes.add_tags {
+        let tag = validate_tag(tag)?;
+        if !tags.contains(&tag) {
+            descriptions.push(format!("added tag '{}'", tag));
+            tags.push(tag);
+            tags_changed = true;
+        }
+    }
+    for tag in &changes.remove_tags {
+        if let Some(position) = tags.iter().position(|t| t == tag) {
+            tags.remove(position);
+            descriptions.push(format!("removed tag '{}'", tag));
+            tags_changed = true;
+        }
+    }
+    if tags_changed {
+        wd.tags = Some(tags).filter(|tags| !tags.is_empty());
+    }
+
+    if let Some(branch_name) = &changes.branch_name {
+        let branch_name = validate_id(branch_name)?;
+        if branch_name.starts_with('-') {
+            return Err(Error::msg("Branch name can't start with '-'"));
+        }
+        if wd.branch_name() != &branch_name {
+            descriptions.push(format!("set branch name to '{}'", branch_name));
+            wd.branch_name = Some(branch_name);
+        }
+    }
+
+    if let Some(due) = changes.due {
+        if wd.due != Some(due) {
+            wd.due = Some(due);
+            descriptions.push(format!("set due date to {}", due.format(DATE_FORMAT)));
+        }
+    } else if changes.remove_due && wd.due.is_some() {
+        wd.due = None;
+        descriptions.push(String::from("removed due date"));
+    }
+
+    Ok(descriptions)
 }
 
-fn delete_whatdo(whatdo: &Whatdo, id: &str) -> Whatdo {
-    debug_assert!(whatdo.id != id);
-    let mut new_whatdo = whatdo.clone();
-    if let Some(queue) = &mut new_whatdo.queue {
-        let found = queue.iter().position(|i| i == id);
-        if let Some(found) = found {
-            queue.remove(found);
+pub fn edit(
+    file: &Path,
+    id: &str,
+    changes: &WhatdoEdit,
+    commit: bool,
+    push: bool,
+) -> Result<Whatdo> {
+    let mut whatdo = parse_file(file)?;
+    let old_branch_name = match find_whatdo(&whatdo, id) {
+        None => return Err(Error::msg(format!("Whatdo '{}' not found", id))),
+        Some(wd) => wd.branch_name().to_owned(),
+    };
+
+    let wd = find_whatdo_mut(&mut whatdo, &|wd| wd.id == id).unwrap();
+    let descriptions = apply_edit(wd, changes)?;
+    if descriptions.is_empty() {
+        return Err(Error::msg("Nothing to change"));
+    }
+    wd.updated = Some(today());
+    let edited = wd.clone();
+
+    let new_branch_name = edited.branch_name();
+    let renames_branch = new_branch_name != &old_branch_name;
+    if renames_branch {
+        if find_whatdo_and_parent(&whatdo, &|wd| {
+            wd.id != id && wd.branch_name() == new_branch_name
+        })
+        .is_some()
+        {
+            return Err(Error::msg(format!(
+                "Whatdo with branch name '{}' already exists",
+                new_branch_name
+            )));
+        }
+
+        if uses_git(file)? && git::branch_exists(new_branch_name)? {
+            return Err(Error::msg(format!(
+                "Branch with name '{}' already exists",
+                new_branch_name
+            )));
         }
     }
 
-    if let Some(whatdos) = &mut new_whatdo.whatdos {
-        let found = whatdos.iter().position(|wd| wd.id == id);
-        if let Some(found) = found {
-            whatdos.remove(found);
+    let touched = write_to_file(file, &whatdo)?;
+
+    if uses_git(file)? {
+        // Keep the whatdo's branch if it was already started
+        if renames_branch && git::branch_exists(&old_branch_name)? {
+            touched.roll_back_on_error(git::rename_branch(&old_branch_name, new_branch_name))?;
+        }
+
+        if commit {
+            touched.commit(
+                &format!("Edited whatdo '{}': {}", id, descriptions.join(", ")),
+                push,
+            )?;
         }
     }
 
-    new_whatdo.whatdos = new_whatdo
-        .whatdos
-        .map(|whatdos| whatdos.iter().map(|wd| delete_whatdo(wd, id)).collect());
+    Ok(edited)
+}
 
-    return new_whatdo;
+/// Remove the whatdo with the given ID from the tree and return it
+fn take_whatdo(root: &mut Whatdo, id: &str) -> Option<Whatdo> {
+    let whatdos = root.whatdos.as_mut()?;
+    if let Some(position) = whatdos.iter().position(|wd| wd.id == id) {
+        return Some(whatdos.remove(position));
+    }
+    whatdos.iter_mut().find_map(|wd| take_whatdo(wd, id))
 }
 
-pub fn delete(id: &str, commit: bool) -> Result<()> {
-    let current_file = get_current_file()?;
-    let whatdo = parse_file(&current_file)?;
-    let new_whatdo = delete_whatdo(&whatdo, id);
-    write_to_file(&new_whatdo)?;
-    if commit {
-        git::commit(
-            [current_file],
-            &format!("Deleted '{}' from whatdos", id),
-            true,
-        )?;
+/// Update queue and blocker references to a whatdo whose ID changed
+fn rename_references(wd: &mut Whatdo, old_id: &str, new_id: &str) {
+    for ids in [&mut wd.queue, &mut wd.blocked_by].into_iter().flatten() {
+        for i in ids.iter_mut().filter(|i| i.as_str() == old_id) {
+            *i = new_id.to_owned();
+        }
+    }
+
+    for subwhatdo in wd.whatdos.iter_mut().flatten() {
+        rename_references(subwhatdo, old_id, new_id);
     }
-    Ok(())
 }
 
-pub fn resolve(id: &str, commit: bool) -> Result<()> {
-    let current_file = get_current_file()?;
-    let whatdo = parse_file(&current_file)?;
-    let new_whatdo = delete_whatdo(&whatdo, id);
-    write_to_file(&new_whatdo)?;
-    if commit {
-        git::commit([current_file], &format!("Resolved whatdo '{}'", id), true)?;
+/// Remove the whatdo with the given ID from the queues of whatdos it is no
+/// longer a descendant of
+fn remove_stale_queue_entries(wd: &mut Whatdo, id: &str) {
+    let is_descendant = wd
+        .whatdos()
+        .iter()
+        .any(|subwhatdo| find_whatdo(subwhatdo, id).is_some());
+    if !is_descendant {
+        if let Some(queue) = &mut wd.queue {
+            queue.retain(|i| i != id);
+        }
+    }
+
+    for subwhatdo in wd.whatdos.iter_mut().flatten() {
+        remove_stale_queue_entries(subwhatdo, id);
     }
-    Ok(())
 }
 
-pub fn finish(commit: bool, merge: bool) -> Result<()> {
-    let current_file = get_current_file()?;
-    let whatdo = parse_file(&current_file)?;
-    let current_wd = match current()? {
-        None => return Err(Error::msg("No active whatdo")),
-        Some(wd) => wd,
+/// Map the ID of each whatdo to the directory of the file its entry is in
+fn collect_entry_dirs(wd: &Whatdo, dir: &Path, dirs: &mut HashMap<String, PathBuf>) {
+    dirs.insert(wd.id.clone(), dir.to_owned());
+    let body_dir = match &wd.include {
+        Some(include) => dir.join(include).parent().unwrap_or(dir).to_owned(),
+        None => dir.to_owned(),
     };
-    let target_branch = find_ancestor_with_branch(&whatdo, &current_wd.id)?
-        .and_then(|p| {
-            if p.id == whatdo.id {
-                whatdo.branch_name.clone()
-            } else {
-                Some(p.branch_name().to_owned())
+    for subwhatdo in wd.whatdos() {
+        let subwhatdo_dir = match &wd.whatdos_dir {
+            Some(whatdos_dir) => {
+                let path = body_dir.join(whatdos_dir).join(&subwhatdo.id);
+                path.parent().unwrap_or(&body_dir).to_owned()
             }
-        })
-        .unwrap_or(git::default_branch_name()?);
-    if merge && git::has_unstaged_changes()? {
-        return Err(Error::msg(
-            "You have unstaged changes. Commit or revert them before finishing whatdo",
-        ));
+            None => body_dir.clone(),
+        };
+        collect_entry_dirs(&subwhatdo, &subwhatdo_dir, dirs);
     }
-    let new_whatdo = delete_whatdo(&whatdo, &current_wd.id);
-    write_to_file(&new_whatdo)?;
-    if commit {
-        git::commit(
-            [current_file],
-            &format!("Finished whatdo '{}'", &current_wd.id),
-            true,
-        )?;
+}
+
+fn has_included_files(wd: &Whatdo) -> bool {
+    wd.include.is_some() || wd.whatdos_dir.is_some() || wd.whatdos().iter().any(has_included_files)
+}
+
+/// Move a whatdo and its sub-whatdos under a new parent and/or give it a new ID
+pub fn move_whatdo(
+    file: &Path,
+    id: &str,
+    new_parent_id: Option<&str>,
+    new_id: Option<&str>,
+    commit: bool,
+    push: bool,
+) -> Result<Whatdo> {
+    let mut whatdo = parse_file(file)?;
+    let (wd, parent) = match find_whatdo_and_parent(&whatdo, &|wd| wd.id == id) {
+        None => return Err(Error::msg(format!("Whatdo '{}' not found", id))),
+        Some((_, None)) => return Err(Error::msg("Cannot move the root whatdo")),
+        Some((wd, Some(parent))) => (wd.clone(), parent.clone()),
+    };
+
+    let new_parent = match new_parent_id {
+        None => parent.clone(),
+        Some(new_parent_id) => match find_whatdo(&whatdo, new_parent_id) {
+            None => return Err(Error::msg(format!("Parent '{}' not found", new_parent_id))),
+            Some(new_parent) => new_parent,
+        },
+    };
+    if find_whatdo(&wd, &new_parent.id).is_some() {
+        return Err(Error::msg(format!(
+            "Cannot move '{}' under itself or one of its sub-whatdos",
+            id
+        )));
     }
-    if merge {
-        git::merge(&target_branch, true)?;
+
+    let new_id = match new_id {
+        None => id.to_owned(),
+        Some(new_id) => validate_id(new_id)?,
+    };
+    if new_id != id && find_whatdo(&whatdo, &new_id).is_some() {
+        return Err(Error::msg(format!(
+            "Whatdo with ID '{}' already exists",
+            new_id
+        )));
+    }
+    if new_id == id && new_parent.id == parent.id {
+        return Err(Error::msg("Nothing to change"));
+    }
+
+    let mut moved = wd.clone();
+    moved.id = new_id.clone();
+    let old_branch_name = wd.branch_name();
+    let new_branch_name = moved.branch_name();
+    let renames_branch = new_branch_name != old_branch_name;
+    if renames_branch {
+        if find_whatdo_and_parent(&whatdo, &|other| {
+            other.id != id && other.branch_name() == new_branch_name
+        })
+        .is_some()
+        {
+            return Err(Error::msg(format!(
+                "Whatdo with branch name '{}' already exists",
+                new_branch_name
+            )));
+        }
+
+        if uses_git(file)? && git::branch_exists(new_branch_name)? {
+            return Err(Error::msg(format!(
+                "Branch with name '{}' already exists",
+                new_branch_name
+            )));
+        }
+    }
+
+    let file_dir = file.parent().unwrap_or(Path::new("."));
+    let mut old_entry_dirs = HashMap::new();
+    collect_entry_dirs(&whatdo, file_dir, &mut old_entry_dirs);
+
+    let mut descriptions = Vec::new();
+    if new_parent.id == parent.id {
+        find_whatdo_mut(&mut whatdo, &|wd| wd.id == id).unwrap().id = new_id.clone();
+    } else {
+        let mut taken = take_whatdo(&mut whatdo, id).unwrap();
+        taken.id = new_id.clone();
+        find_whatdo_mut(&mut whatdo, &|wd| wd.id == new_parent.id)
+            .unwrap()
+            .whatdos
+            .get_or_insert_with(Vec::new)
+            .push(taken);
+        descriptions.push(format!("moved under '{}'", new_parent.id));
     }
-    Ok(())
-}
 
-pub fn init() -> Result<PathBuf> {
-    let current_file = get_current_file()?;
-    if current_file.exists() {
+    // Included paths are relative to the file containing the whatdo, so they
+    // would need to be rewritten if the whatdo ended up in another directory
+    let mut new_entry_dirs = HashMap::new();
+    collect_entry_dirs(&whatdo, file_dir, &mut new_entry_dirs);
+    if old_entry_dirs[id] != new_entry_dirs[&new_id] && has_included_files(&wd) {
         return Err(Error::msg(format!(
-            "Whatdo file already exists at {}",
-            current_file.to_string_lossy()
+            "Cannot move '{}' to a different directory because it uses included files",
+            id
         )));
     }
 
-    let initial_content = sample::initial_whatdo_file();
-    write_to_file(&initial_content)?;
-    Ok(current_file)
+    if new_id != id {
+        rename_references(&mut whatdo, id, &new_id);
+        descriptions.push(format!("renamed to '{}'", new_id));
+    }
+    remove_stale_queue_entries(&mut whatdo, &new_id);
+
+    let touched = write_to_file(file, &whatdo)?;
+
+    if uses_git(file)? {
+        // Keep the whatdo's branch if it was already started
+        if renames_branch && git::branch_exists(old_branch_name)? {
+            touched.roll_back_on_error(git::rename_branch(old_branch_name, new_branch_name))?;
+        }
+
+        if commit {
+            touched.commit(
+                &format!("Moved whatdo '{}': {}", id, descriptions.join(", ")),
+                push,
+            )?;
+        }
+    }
+
+    Ok(find_whatdo(&whatdo, &new_id).unwrap())
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use pretty_assertions::assert_eq;
+pub enum QueueChange {
+    /// Add a whatdo to the queue, at the end unless a position is given
+    Add {
+        id: String,
+        at: Option<usize>,
+    },
+    Remove {
+        id: String,
+    },
+    Move {
+        id: String,
+        to: usize,
+    },
+}
 
-    fn test_data_whatdo() -> Whatdo {
-        Whatdo {
-            id: String::from("test_data"),
-            summary: Some(String::from(
-                "A streamlined git-based tool for task tracking of a project",
-            )),
-            whatdos: Some(vec![Whatdo {
-                id: String::from("basic-functionality"),
-                summary: Some(String::from(
-                    "Implement the absolute minimum stuff for the tool to get it to be useful
-for tracking the progress of this tool\n",
-                )),
-                whatdos: Some(vec![
-                    Whatdo::simple(
-                        String::from("read-back-whatdos"),
+/// Apply a change to the queue of `owner` and return a description of it
+fn apply_queue_change(owner: &mut Whatdo, change: &QueueChange) -> Result<String> {
+    let queue = owner.queue.clone().unwrap_or_default();
+    let position_in_queue = |id: &str| {
+        queue.iter().position(|i| i == id).ok_or(Error::msg(format!(
+            "Whatdo '{}' is not in the queue of '{}'",
+            id, owner.id
+        )))
+    };
+    let check_position = |position: usize, len: usize| {
+        if position > len {
+            return Err(Error::msg(format!(
+                "Position {} is past the end of the queue of '{}'",
+                position, owner.id
+            )));
+        }
+        Ok(position)
+    };
+
+    let mut new_queue = queue.clone();
+    let description = match change {
+        QueueChange::Add { id, at } => {
+            if id == &owner.id || find_whatdo(owner, id).is_none() {
+                return Err(Error::msg(format!(
+                    "Whatdo '{}' is not a sub-whatdo of '{}'",
+                    id, owner.id
+                )));
+            }
+            if queue.contains(id) {
+                return Err(Error::msg(format!(
+                    "Whatdo '{}' is already in the queue of '{}'",
+                    id, owner.id
+                )));
+            }
+            let position = check_position(at.unwrap_or(queue.len()), queue.len())?;
+            new_queue.insert(position, id.clone());
+            format!("Added '{}' to the queue of '{}'", id, owner.id)
+        }
+        QueueChange::Remove { id } => {
+            new_queue.remove(position_in_queue(id)?);
+            format!("Removed '{}' from the queue of '{}'", id, owner.id)
+        }
+        QueueChange::Move { id, to } => {
+            let item = new_queue.remove(position_in_queue(id)?);
+            let position = check_position(*to, new_queue.len())?;
+            new_queue.insert(position, item);
+            if new_queue == queue {
+                return Err(Error::msg("Nothing to change"));
+            }
+            format!(
+                "Moved '{}' to position {} in the queue of '{}'",
+                id, position, owner.id
+            )
+        }
+    };
+
+    owner.queue = if new_queue.is_empty() {
+        None
+    } else {
+        Some(new_queue)
+    };
+    Ok(description)
+}
+
+/// Return the whatdo owning the queue, or the root if no ID is given
+fn find_queue_owner(root: &Whatdo, owner_id: Option<&str>) -> Result<Whatdo> {
+    let owner_id = owner_id.unwrap_or(&root.id);
+    find_whatdo(root, owner_id).ok_or(Error::msg(format!("Whatdo '{}' not found", owner_id)))
+}
+
+/// The queue of a whatdo. Queue items that aren't sub-whatdos of the owner
+/// have no whatdo
+pub struct Queue {
+    pub owner: Whatdo,
+    pub items: Vec<(String, Option<Whatdo>)>,
+}
+
+impl fmt::Display for Queue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.items.is_empty() {
+            return writeln!(f, "The queue of '{}' is empty", self.owner.id);
+        }
+
+        writeln!(f, "Queue of '{}':", self.owner.id)?;
+        for (position, (id, wd)) in self.items.iter().enumerate() {
+            match wd {
+                None => writeln!(f, "{}: [{}] Not found", position, id)?,
+                Some(wd) => writeln!(f, "{}: {}", position, wd)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn queue(file: &Path, owner_id: Option<&str>) -> Result<Queue> {
+    let whatdo = parse_file(file)?;
+    let owner = find_queue_owner(&whatdo, owner_id)?;
+    let items = owner
+        .queue
+        .iter()
+        .flatten()
+        .map(|id| {
+            (
+                id.clone(),
+                find_whatdo(&owner, id).filter(|wd| wd.id != owner.id),
+            )
+        })
+        .collect();
+    Ok(Queue { owner, items })
+}
+
+pub fn edit_queue(
+    file: &Path,
+    owner_id: Option<&str>,
+    change: &QueueChange,
+    commit: bool,
+    push: bool,
+) -> Result<Queue> {
+    let mut whatdo = parse_file(file)?;
+    let owner_id = find_queue_owner(&whatdo, owner_id)?.id;
+    let owner = find_whatdo_mut(&mut whatdo, &|wd| wd.id == owner_id).unwrap();
+    let description = apply_queue_change(owner, change)?;
+
+    let touched = write_to_file(file, &whatdo)?;
+    if commit && uses_git(file)? {
+        touched.commit(&description, push)?;
+    }
+
+    queue(file, Some(&owner_id))
+}
+
+/// How sibling whatdos are ordered. Queues and blockers take precedence over
+/// all sort orders
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Lower priority values first
+    Priority,
+    /// Oldest first, by created date or by updated date if there is none
+    Chronological,
+    /// Earliest due date of the whatdo or its sub-whatdos first
+    Due,
+    /// Lowest score from `weighted_score` first
+    Weighted,
+    /// Shuffled, but always in the same order for the same seed
+    Random(u64),
+}
+
+/// Whatdos without a priority are scored as if they had this one
+const WEIGHTED_DEFAULT_PRIORITY: i64 = 10;
+
+/// Score combining priority, due date and age, where lower scores go first.
+/// A priority level is worth a week until the whatdo (or one of its
+/// sub-whatdos) is due, and a whatdo gains a day for each week since it was
+/// created. Due dates and ages further than four weeks out count as four weeks
+fn weighted_score(wd: &Whatdo) -> i64 {
+    const MAX_DAYS: i64 = 28;
+    let priority = wd.priority.unwrap_or(WEIGHTED_DEFAULT_PRIORITY);
+    let days_until_due = wd
+        .earliest_due_date()
+        .map_or(MAX_DAYS, |due| (due - today()).num_days().min(MAX_DAYS));
+    let weeks_old = wd
+        .created
+        .map_or(0, |created| (today() - created).num_days() / 7);
+    7 * priority + days_until_due - weeks_old.clamp(0, MAX_DAYS / 7)
+}
+
+/// Key that `SortOrder::Random` sorts by. This is a hash of the seed and ID
+/// with FNV-1a, which unlike the standard library's hasher is stable across
+/// builds
+fn random_key(seed: u64, id: &str) -> u64 {
+    seed.to_le_bytes()
+        .iter()
+        .chain(id.as_bytes())
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+impl SortOrder {
+    /// Whatdos without the field being sorted on go last. Otherwise equal
+    /// whatdos keep their order in the file
+    fn compare(&self, a: &Whatdo, b: &Whatdo) -> std::cmp::Ordering {
+        fn some_first<T: Ord>(a: Option<T>, b: Option<T>) -> std::cmp::Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, None) => std::cmp::Ordering::Equal,
+            }
+        }
+
+        match self {
+            SortOrder::Priority => some_first(a.priority, b.priority)
+                .then_with(|| some_first(a.soon_due_date(), b.soon_due_date())),
+            SortOrder::Chronological => {
+                some_first(a.created.or(a.updated), b.created.or(b.updated))
+            }
+            SortOrder::Due => some_first(a.earliest_due_date(), b.earliest_due_date()),
+            SortOrder::Weighted => weighted_score(a).cmp(&weighted_score(b)),
+            SortOrder::Random(seed) => random_key(*seed, &a.id).cmp(&random_key(*seed, &b.id)),
+        }
+    }
+}
+
+const SORT_ORDER_NAMES: [&str; 5] = ["priority", "chronological", "due", "weighted", "random"];
+
+impl FromStr for SortOrder {
+    type Err = Error;
+
+    /// Parse the name of a sort order. `random` is seeded with the current
+    /// date unless a seed is given like `random:42`
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "priority" => Ok(SortOrder::Priority),
+            "chronological" => Ok(SortOrder::Chronological),
+            "due" => Ok(SortOrder::Due),
+            "weighted" => Ok(SortOrder::Weighted),
+            "random" => Ok(SortOrder::Random(today().num_days_from_ce() as u64)),
+            _ => match s.strip_prefix("random:") {
+                Some(seed) => match seed.parse() {
+                    Ok(seed) => Ok(SortOrder::Random(seed)),
+                    Err(_) => Err(Error::msg(format!(
+                        "Expected the seed of '{}' to be a non-negative integer",
+                        s
+                    ))),
+                },
+                None => Err(Error::msg(format!(
+                    "Unknown sort order '{}'. Expected one of: {}",
+                    s,
+                    SORT_ORDER_NAMES.join(", ")
+                ))),
+            },
+        }
+    }
+}
+
+/// The sort order set with `sort` in the root mapping of the whatdo file, or
+/// priority order if there is none
+fn default_sort_order(root: &Whatdo) -> Result<SortOrder> {
+    match root.extra.get("sort") {
+        None => Ok(SortOrder::Priority),
+        Some(serde_yaml::Value::String(s)) => s.parse(),
+        Some(_) => Err(Error::msg("Expected 'sort' to be a string")),
+    }
+}
+
+pub enum NextAmount {
+    All,
+    AtMost(usize),
+}
+
+/// A whatdo to do next, along with the IDs of its ancestors
+pub struct NextWhatdo {
+    pub whatdo: Whatdo,
+    /// Starts from the root and ends with the parent
+    pub path: Vec<String>,
+}
+
+impl NextWhatdo {
+    /// The `n`th ancestor of the whatdo, where the parent is 1. The root can't
+    /// be returned
+    pub fn ancestor_id(&self, n: usize) -> Result<&str> {
+        let startable = self.path.len().saturating_sub(1);
+        if n == 0 || n > startable {
+            return Err(Error::msg(format!(
+                "'{}' has {} ancestors besides the root, so it has no ancestor {}",
+                self.whatdo.id, startable, n
+            )));
+        }
+        Ok(&self.path[self.path.len() - n])
+    }
+
+    /// The path to the whatdo, like `project > parent`, or None for whatdos at
+    /// the top of the file
+    pub fn breadcrumb(&self) -> Option<String> {
+        if self.path.len() > 1 {
+            Some(self.path.join(" > "))
+        } else {
+            None
+        }
+    }
+}
+
+pub fn next(
+    file: &Path,
+    amount: NextAmount,
+    query: Option<Query>,
+    order: Option<SortOrder>,
+) -> Result<Vec<NextWhatdo>> {
+    let root = parse_file(file)?;
+    let order = match order {
+        Some(order) => order,
+        None => default_sort_order(&root)?,
+    };
+    let current_wd = current(file)?;
+    let mut visited = HashSet::new();
+    if let Some(current_id) = current_wd.clone().map(|c| c.id) {
+        visited.insert(current_id);
+    }
+
+    let filter = query_filter(&root, query);
+
+    let mut current_sorted = if let Some(wd) = current_wd.clone() {
+        sort_whatdos(&wd, &filter, order, &mut visited, false)
+    } else {
+        vec![]
+    };
+
+    let mut rest_sorted = sort_whatdos(&root, &filter, order, &mut visited, false);
+    current_sorted.append(&mut rest_sorted);
+    let sorted = order_by_blockers(&root, current_sorted)?;
+    let sorted = match amount {
+        NextAmount::All => sorted,
+        NextAmount::AtMost(n) => sorted.into_iter().take(n).collect(),
+    };
+    Ok(sorted
+        .into_iter()
+        .map(|whatdo| NextWhatdo {
+            path: ancestor_ids(&root, &whatdo.id).unwrap_or_default(),
+            whatdo,
+        })
+        .collect())
+}
+
+/// Start working on the whatdo. If its branch already exists, locally or on the
+/// remote, it's checked out instead of created, and with `fast_forward` it's
+/// brought up to date with the branch of its parent. Returns whether the branch
+/// already existed
+pub fn start(file: &Path, wd: &Whatdo, fast_forward: bool, push: bool) -> Result<bool> {
+    if !uses_git(file)? {
+        set_active_id(file, Some(&wd.id))?;
+        return Ok(false);
+    }
+
+    let resumed = checkout_whatdo_branch(wd, None, push)?;
+    if resumed && fast_forward {
+        git::fast_forward(&parent_branch(&parse_file(file)?, &wd.id)?)?;
+    }
+    Ok(resumed)
+}
+
+/// Check out the branch of the whatdo. A branch that only exists on the remote
+/// is checked out as a local branch tracking it. A branch that doesn't exist at
+/// all is created from the start point, or from HEAD, and pushed. Returns
+/// whether the branch already existed
+fn checkout_whatdo_branch(wd: &Whatdo, start_point: Option<&str>, push: bool) -> Result<bool> {
+    let branch_name = wd.branch_name();
+    if git::branch_exists(branch_name)? {
+        git::checkout(branch_name)?;
+        return Ok(true);
+    }
+
+    match push_remote()? {
+        Some(remote) if git::remote_branch_exists(&remote, branch_name)? => {
+            git::checkout_remote_branch(&remote, branch_name)?;
+            Ok(true)
+        }
+        _ => {
+            git::checkout_new_branch(branch_name, start_point)?;
+            push_or_queue(branch_name, push)?;
+            Ok(false)
+        }
+    }
+}
+
+/// How work in progress is set aside when switching away from a whatdo
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parking {
+    Stash,
+    /// A commit that is undone when switching back
+    WipCommit,
+}
+
+/// What happened to work in progress when switching whatdos
+pub struct Switch {
+    /// How changes on the previous branch were set aside, if there were any
+    pub parked: Option<Parking>,
+    /// How changes on the new branch were brought back, if there were any
+    pub restored: Option<Parking>,
+}
+
+/// Message identifying work that `wd switch` set aside on the branch
+fn parked_message(branch_name: &str) -> String {
+    format!("wd switch: work in progress on '{}'", branch_name)
+}
+
+/// Switch to working on a different whatdo. Uncommitted changes on the current
+/// branch are set aside, and any that were set aside on the whatdo's branch are
+/// brought back. If the whatdo's branch doesn't exist yet, it's created from the
+/// branch of its parent
+pub fn switch(file: &Path, wd: &Whatdo, parking: Parking, push: bool) -> Result<Switch> {
+    if !uses_git(file)? {
+        set_active_id(file, Some(&wd.id))?;
+        return Ok(Switch {
+            parked: None,
+            restored: None,
+        });
+    }
+
+    let current_branch = git::current_branch()?;
+    let target_branch = wd.branch_name();
+    if &current_branch == target_branch {
+        return Err(Error::msg(format!("Already on the branch of '{}'", wd.id)));
+    }
+
+    // A new branch starts from the branch of its parent, so that it doesn't
+    // include a WIP commit parked on the current branch
+    let start_point = if git::branch_exists(target_branch)? {
+        None
+    } else {
+        Some(parent_branch(&parse_file(file)?, &wd.id)?)
+    };
+
+    let parked = if git::has_unstaged_changes()? {
+        let message = parked_message(&current_branch);
+        match parking {
+            Parking::Stash => git::stash(&message)?,
+            Parking::WipCommit => git::commit_all(&message)?,
+        }
+        Some(parking)
+    } else {
+        None
+    };
+
+    checkout_whatdo_branch(wd, start_point.as_deref(), push)?;
+
+    let message = parked_message(target_branch);
+    let restored = if git::head_commit_message()? == message {
+        git::uncommit()?;
+        Some(Parking::WipCommit)
+    } else if let Some(stash) = git::find_stash(&message)? {
+        git::stash_pop(&stash)?;
+        Some(Parking::Stash)
+    } else {
+        None
+    };
+
+    Ok(Switch { parked, restored })
+}
+
+pub fn get(file: &Path, id: &str) -> Result<Option<Whatdo>> {
+    let whatdo = parse_file(file)?;
+    Ok(find_whatdo(&whatdo, id))
+}
+
+pub fn root(file: &Path) -> Result<Option<Whatdo>> {
+    if !file.exists() {
+        return Ok(None);
+    }
+
+    Ok(Some(parse_file(file)?))
+}
+
+pub fn current(file: &Path) -> Result<Option<Whatdo>> {
+    let whatdo = parse_file(file)?;
+    if !uses_git(file)? {
+        return Ok(active_id(file)?.and_then(|id| find_whatdo(&whatdo, &id)));
+    }
+
+    let current_branch = git::current_branch()?;
+    if let Some((wd, _)) =
+        find_whatdo_and_parent(&whatdo, &|wd| wd.branch_name() == &current_branch)
+    {
+        return Ok(Some(wd.clone()));
+    }
+    Ok(None)
+}
+
+/// Resolve an ID relative to the current whatdo. `@` is the current whatdo,
+/// each `^` after it is a parent up from that, and `/<id>` after that is a
+/// sub-whatdo of it at any depth, as in `@^/other-child`. IDs that don't start
+/// with `@` are returned as-is
+pub fn resolve_id(file: &Path, id: &str) -> Result<String> {
+    let relative = match id.strip_prefix('@') {
+        None => return Ok(id.to_owned()),
+        Some(relative) => relative,
+    };
+    let (parents, child) = match relative.split_once('/') {
+        None => (relative, None),
+        Some((parents, child)) => (parents, Some(child)),
+    };
+    if parents.chars().any(|c| c != '^') || child == Some("") {
+        return Err(Error::msg(format!(
+            "Expected an ID like '@', '@^' or '@/<id>', got '{}'",
+            id
+        )));
+    }
+
+    let root = parse_file(file)?;
+    let mut resolved = match current(file)? {
+        Some(wd) => wd,
+        None if uses_git(file)? => {
+            return Err(Error::msg(format!(
+                "No whatdo matches the current branch '{}', so '{}' can't be resolved",
+                git::current_branch()?,
+                id
+            )))
+        }
+        None => {
+            return Err(Error::msg(format!(
+                "No whatdo is active, so '{}' can't be resolved. Start one with `wd start`",
+                id
+            )))
+        }
+    };
+
+    for _ in parents.chars() {
+        resolved = match find_parent(&root, &resolved.id) {
+            Some(parent) if parent.id != root.id => parent,
+            _ => {
+                return Err(Error::msg(format!(
+                    "'{}' has no parent, so '{}' can't be resolved",
+                    resolved.id, id
+                )))
+            }
+        };
+    }
+
+    match child {
+        None => Ok(resolved.id),
+        Some(child) => match find_whatdo(&resolved, child) {
+            Some(wd) if wd.id != resolved.id => Ok(wd.id),
+            _ => Err(Error::msg(format!(
+                "'{}' has no sub-whatdo '{}'",
+                resolved.id, child
+            ))),
+        },
+    }
+}
+
+fn delete_whatdo(whatdo: &Whatdo, id: &str) -> Whatdo {
+    debug_assert!(whatdo.id != id);
+    let mut new_whatdo = whatdo.clone();
+    if let Some(queue) = &mut new_whatdo.queue {
+        let found = queue.iter().position(|i| i == id);
+        if let Some(found) = found {
+            queue.remove(found);
+        }
+    }
+
+    if let Some(blocked_by) = &mut new_whatdo.blocked_by {
+        blocked_by.retain(|i| i != id);
+    }
+
+    if let Some(whatdos) = &mut new_whatdo.whatdos {
+        let found = whatdos.iter().position(|wd| wd.id == id);
+        if let Some(found) = found {
+            whatdos.remove(found);
+        }
+    }
+
+    new_whatdo.whatdos = new_whatdo
+        .whatdos
+        .map(|whatdos| whatdos.iter().map(|wd| delete_whatdo(wd, id)).collect());
+
+    return new_whatdo;
+}
+
+pub fn delete(file: &Path, id: &str, commit: bool, push: bool) -> Result<()> {
+    let whatdo = parse_file(file)?;
+    let new_whatdo = delete_whatdo(&whatdo, id);
+    let touched = write_to_file(file, &new_whatdo)?;
+    if commit && uses_git(file)? {
+        touched.commit(&format!("Deleted '{}' from whatdos", id), push)?;
+    }
+    Ok(())
+}
+
+pub fn resolve(file: &Path, id: &str, commit: bool, push: bool) -> Result<()> {
+    let whatdo = parse_file(file)?;
+    let touched = archive_whatdo(file, &whatdo, id)?;
+    if commit && uses_git(file)? {
+        touched.commit(&format!("Resolved whatdo '{}'", id), push)?;
+    }
+    Ok(())
+}
+
+pub fn finish(file: &Path, commit: bool, merge: bool, push: bool) -> Result<()> {
+    let whatdo = parse_file(file)?;
+    let current_wd = match current(file)? {
+        None => return Err(Error::msg("No active whatdo")),
+        Some(wd) => wd,
+    };
+    if !uses_git(file)? {
+        archive_whatdo(file, &whatdo, &current_wd.id)?;
+        return set_active_id(file, None);
+    }
+
+    let target_branch = parent_branch(&whatdo, &current_wd.id)?;
+    if merge && git::has_unstaged_changes()? {
+        return Err(Error::msg(
+            "You have unstaged changes. Commit or revert them before finishing whatdo",
+        ));
+    }
+    let touched = archive_whatdo(file, &whatdo, &current_wd.id)?;
+    if commit {
+        touched.commit(&format!("Finished whatdo '{}'", &current_wd.id), push)?;
+    }
+    if merge {
+        git::merge(&target_branch)?;
+        push_or_queue(&target_branch, push)?;
+    }
+    Ok(())
+}
+
+/// A resolved whatdo kept in the archive file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ArchivedWhatdo {
+    pub whatdo: Whatdo,
+    /// ID of the whatdo's parent when it was resolved
+    pub parent_id: String,
+    pub resolved_at: DateTime<Utc>,
+    /// Branch the whatdo was resolved on, if using git
+    pub branch: Option<String>,
+    /// Commit checked out just before the whatdo was resolved, if using git.
+    /// The commit that resolves the whatdo comes after this one, since a
+    /// commit can't record its own hash
+    pub commit: Option<String>,
+}
+
+impl fmt::Display for ArchivedWhatdo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\n    Resolved {}",
+            self.whatdo,
+            self.resolved_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+        )?;
+        if let Some(branch) = &self.branch {
+            write!(f, " on '{}'", branch)?;
+        }
+        if let Some(commit) = &self.commit {
+            write!(f, " after {}", &commit[..commit.len().min(7)])?;
+        }
+        Ok(())
+    }
+}
+
+impl ArchivedWhatdo {
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "whatdo": whatdo_json_with_path(&self.whatdo, &[]),
+            "parent": self.parent_id,
+            "resolved_at": self.resolved_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "branch": self.branch,
+            "commit": self.commit,
+        })
+    }
+}
+
+/// The file resolved whatdos are archived in, e.g. WHATDO.done.yaml
+fn archive_file(file: &Path) -> PathBuf {
+    file.with_extension("done.yaml")
+}
+
+/// Copy of the whatdo with all of its subtrees stored inline, so that it
+/// doesn't depend on any included files
+fn inline_whatdo(wd: &Whatdo) -> Whatdo {
+    let mut inlined = wd.clone();
+    inlined.include = None;
+    inlined.whatdos_dir = None;
+    inlined.whatdos = wd
+        .whatdos
+        .as_ref()
+        .map(|whatdos| whatdos.iter().map(inline_whatdo).collect());
+    inlined
+}
+
+fn serialize_archived_whatdo(archived: &ArchivedWhatdo) -> serde_yaml::Value {
+    let mut mapping = Mapping::new();
+    mapping.insert(
+        serde_yaml::Value::from("id"),
+        serde_yaml::Value::from(archived.whatdo.id.clone()),
+    );
+    mapping.insert(
+        serde_yaml::Value::from("parent"),
+        serde_yaml::Value::from(archived.parent_id.clone()),
+    );
+    mapping.insert(
+        serde_yaml::Value::from("resolved_at"),
+        serde_yaml::Value::from(
+            archived
+                .resolved_at
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        ),
+    );
+    if let Some(branch) = &archived.branch {
+        mapping.insert(
+            serde_yaml::Value::from("branch"),
+            serde_yaml::Value::from(branch.clone()),
+        );
+    }
+    if let Some(commit) = &archived.commit {
+        mapping.insert(
+            serde_yaml::Value::from("commit"),
+            serde_yaml::Value::from(commit.clone()),
+        );
+    }
+    mapping.insert(
+        serde_yaml::Value::from("whatdo"),
+        serialize_whatdo_body(&archived.whatdo),
+    );
+    serde_yaml::Value::Mapping(mapping)
+}
+
+fn parse_archived_whatdo(data: &serde_yaml::Value, dir: &Path) -> Result<ArchivedWhatdo> {
+    let get_str = |key: &str| -> Result<Option<String>> {
+        match data.get(key) {
+            None => Ok(None),
+            Some(serde_yaml::Value::String(s)) => Ok(Some(s.clone())),
+            Some(_) => Err(Error::msg(format!(
+                "Archived whatdo '{}' must be a string",
+                key
+            ))),
+        }
+    };
+    let require_str = |key: &str| -> Result<String> {
+        get_str(key)?.ok_or(Error::msg(format!("Archived whatdo is missing '{}'", key)))
+    };
+
+    let id = require_str("id")?;
+    let body = data
+        .get("whatdo")
+        .cloned()
+        .unwrap_or(serde_yaml::Value::Null);
+    let resolved_at = DateTime::parse_from_rfc3339(&require_str("resolved_at")?)
+        .map_err(|e| Error::msg(format!("Invalid resolved_at of '{}': {}", id, e)))?
+        .with_timezone(&Utc);
+    Ok(ArchivedWhatdo {
+        whatdo: parse_whatdo(&id, &body, dir)?,
+        parent_id: require_str("parent")?,
+        resolved_at,
+        branch: get_str("branch")?,
+        commit: get_str("commit")?,
+    })
+}
+
+fn parse_archive(file: &Path) -> Result<Vec<ArchivedWhatdo>> {
+    let path = archive_file(file);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let data: serde_yaml::Value = serde_yaml::from_str(&std::fs::read_to_string(&path)?)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    match data {
+        serde_yaml::Value::Null => Ok(Vec::new()),
+        serde_yaml::Value::Sequence(entries) => entries
+            .iter()
+            .map(|entry| parse_archived_whatdo(entry, dir))
+            .collect(),
+        _ => Err(Error::msg("Archive file must be a list of whatdos")),
+    }
+}
+
+fn write_archive(file: &Path, archive: &[ArchivedWhatdo], touched: &mut Touched) -> Result<()> {
+    let value =
+        serde_yaml::Value::Sequence(archive.iter().map(serialize_archived_whatdo).collect());
+    write_yaml(&archive_file(file), &value, touched)
+}
+
+/// Remove the whatdo from the tree and add it to the archive. Returns the
+/// files written or removed
+fn archive_whatdo(file: &Path, whatdo: &Whatdo, id: &str) -> Result<Touched> {
+    let (wd, parent) = match find_whatdo_and_parent(whatdo, &|wd| wd.id == id) {
+        None => return Err(Error::msg(format!("Whatdo '{}' not found", id))),
+        Some((_, None)) => return Err(Error::msg("Cannot resolve the root whatdo")),
+        Some((wd, Some(parent))) => (wd, parent),
+    };
+
+    let (branch, commit) = if uses_git(file)? {
+        (Some(git::current_branch()?), git::head_commit()?)
+    } else {
+        (None, None)
+    };
+
+    let mut archive = parse_archive(file)?;
+    archive.push(ArchivedWhatdo {
+        whatdo: inline_whatdo(wd),
+        parent_id: parent.id.clone(),
+        resolved_at: Utc::now(),
+        branch,
+        commit,
+    });
+
+    let mut touched = write_to_file(file, &delete_whatdo(whatdo, id))?;
+    write_archive(file, &archive, &mut touched)?;
+    Ok(touched)
+}
+
+/// Return the resolved whatdos, oldest first
+pub fn archive(file: &Path) -> Result<Vec<ArchivedWhatdo>> {
+    parse_archive(file)
+}
+
+/// Restore the most recently resolved whatdo with the given ID to its
+/// original parent, or to the root if that parent no longer exists. Returns
+/// the whatdo and its new parent
+pub fn reopen(file: &Path, id: &str, commit: bool, push: bool) -> Result<(Whatdo, Whatdo)> {
+    let mut whatdo = parse_file(file)?;
+    let mut archive = parse_archive(file)?;
+    let position = match archive.iter().rposition(|a| a.whatdo.id == id) {
+        None => return Err(Error::msg(format!("No resolved whatdo '{}'", id))),
+        Some(position) => position,
+    };
+    let archived = archive.remove(position);
+
+    // Whatdos added since this one was resolved may reuse IDs from its subtree
+    let mut ids = HashSet::new();
+    let mut branch_names = HashSet::new();
+    visit_whatdos(&whatdo, &mut |wd| {
+        ids.insert(wd.id.clone());
+        branch_names.insert(wd.branch_name().clone());
+    });
+    let mut problems = Vec::new();
+    visit_whatdos(&archived.whatdo, &mut |wd| {
+        if ids.contains(&wd.id) {
+            problems.push(format!("Whatdo with ID '{}' already exists", wd.id));
+        } else if branch_names.contains(wd.branch_name()) {
+            problems.push(format!(
+                "Whatdo with branch name '{}' already exists",
+                wd.branch_name()
+            ));
+        }
+    });
+    if !problems.is_empty() {
+        return Err(Error::msg(format!(
+            "Cannot reopen '{}':\n{}",
+            id,
+            problems.join("\n")
+        )));
+    }
+
+    let parent_id = match find_whatdo(&whatdo, &archived.parent_id) {
+        Some(parent) => parent.id,
+        None => whatdo.id.clone(),
+    };
+    let parent = find_whatdo_mut(&mut whatdo, &|wd| wd.id == parent_id).unwrap();
+    parent
+        .whatdos
+        .get_or_insert_with(Vec::new)
+        .push(archived.whatdo.clone());
+    let parent = parent.clone();
+
+    let mut touched = write_to_file(file, &whatdo)?;
+    write_archive(file, &archive, &mut touched)?;
+    if commit && uses_git(file)? {
+        touched.commit(&format!("Reopened whatdo '{}'", id), push)?;
+    }
+
+    Ok((archived.whatdo, parent))
+}
+
+pub fn init(file: &Path) -> Result<()> {
+    if file.exists() {
+        return Err(Error::msg(format!(
+            "Whatdo file already exists at {}",
+            file.to_string_lossy()
+        )));
+    }
+
+    std::fs::write(file, sample::INITIAL_WHATDO_FILE)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// An empty directory for a test to write to, removed when dropped
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> TestDir {
+            let dir = std::env::temp_dir().join(format!("whatdo-test-{}", name));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+
+        /// Copy the test data file into the directory as WHATDO.yaml
+        fn copy(&self, test_data: &str) -> PathBuf {
+            let path = self.0.join("WHATDO.yaml");
+            std::fs::copy(test_data, &path).unwrap();
+            path
+        }
+    }
+
+    impl std::ops::Deref for TestDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn finish_whatdo_extra() -> Mapping {
+        Mapping::from_iter([(
+            serde_yaml::Value::from("owner"),
+            serde_yaml::Value::from("someone"),
+        )])
+    }
+
+    fn test_data_whatdo() -> Whatdo {
+        Whatdo {
+            id: String::from("test_data"),
+            summary: Some(String::from(
+                "A streamlined git-based tool for task tracking of a project",
+            )),
+            whatdos: Some(vec![Whatdo {
+                id: String::from("basic-functionality"),
+                summary: Some(String::from(
+                    "Implement the absolute minimum stuff for the tool to get it to be useful
+for tracking the progress of this tool\n",
+                )),
+                whatdos: Some(vec![
+                    Whatdo::simple(
+                        String::from("read-back-whatdos"),
                         Some(String::from(
                             "Ability to invoke `wd` to list the current whatdos",
                         )),
@@ -864,137 +3152,904 @@ for tracking the progress of this tool\n",
                             "delete-whatdo",
                             Some("Delete the whatdo"),
                         )]),
-                        simple_format: false,
-                        queue: None,
-                        priority: None,
-                        branch_name: None,
+                        extra: finish_whatdo_extra(),
                         tags: Some(vec!["a-tag".to_owned()]),
+                        ..Default::default()
                     },
                 ]),
-                queue: None,
                 priority: Some(0),
-                tags: None,
-                branch_name: None,
-                simple_format: false,
+                ..Default::default()
             }]),
-            simple_format: false,
             queue: Some(vec![
                 String::from("read-back-whatdos"),
                 String::from("delete-whatdo"),
             ]),
-            priority: None,
-            tags: None,
             branch_name: Some(String::from("overridden-name")),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let parsed = parse_file(&PathBuf::from("./test_data/WHATDO.yaml"));
+        assert_eq!(parsed.unwrap(), test_data_whatdo());
+    }
+
+    #[test]
+    fn test_delete_whatdo() {
+        let deleted = delete_whatdo(&test_data_whatdo(), "delete-whatdo");
+        assert_eq!(
+            deleted,
+            Whatdo {
+                id: String::from("test_data"),
+                summary: Some(String::from(
+                    "A streamlined git-based tool for task tracking of a project",
+                )),
+                whatdos: Some(vec![Whatdo {
+                    id: String::from("basic-functionality"),
+                    summary: Some(String::from(
+                        "Implement the absolute minimum stuff for the tool to get it to be useful\nfor tracking the progress of this tool\n",
+                    )),
+                    whatdos: Some(vec![
+                        Whatdo::simple(
+                            String::from("read-back-whatdos"),
+                            Some(String::from(
+                                "Ability to invoke `wd` to list the current whatdos"
+                            )),
+                        ),
+                        Whatdo {
+                            id: String::from("finish-whatdo"),
+                            summary: Some(String::from(
+                                "Ability to invoke `wd finish` to finish the current whatdo",
+                            )),
+                            whatdos: Some(vec![]),
+                            extra: finish_whatdo_extra(),
+                            tags: Some(vec!["a-tag".to_owned()]),
+                            ..Default::default()
+                        },
+                    ]),
+                    priority: Some(0),
+                    ..Default::default()
+                }]),
+                queue: Some(vec![String::from("read-back-whatdos")]),
+                branch_name: Some(String::from("overridden-name")),
+                ..Default::default()
+            }
+        );
+        let deleted_again = delete_whatdo(&deleted, "read-back-whatdos");
+        assert_eq!(
+            deleted_again,
+            Whatdo {
+                id: String::from("test_data"),
+                summary: Some(String::from(
+                    "A streamlined git-based tool for task tracking of a project",
+                )),
+                whatdos: Some(vec![Whatdo {
+                    id: String::from("basic-functionality"),
+                    summary: Some(String::from(
+                        "Implement the absolute minimum stuff for the tool to get it to be useful\nfor tracking the progress of this tool\n",
+                    )),
+                    whatdos: Some(vec![Whatdo {
+                        id: String::from("finish-whatdo"),
+                        summary: Some(String::from(
+                            "Ability to invoke `wd finish` to finish the current whatdo",
+                        )),
+                        whatdos: Some(vec![]),
+                        extra: finish_whatdo_extra(),
+                        tags: Some(vec!["a-tag".to_owned()]),
+                        ..Default::default()
+                    },]),
+                    priority: Some(0),
+                    ..Default::default()
+                }]),
+                queue: Some(vec![]),
+                branch_name: Some(String::from("overridden-name")),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_whatdo_json() {
+        let root = test_data_whatdo();
+        let wd = find_whatdo(&root, "finish-whatdo").unwrap();
+        assert_eq!(
+            whatdo_json(&root, &wd),
+            json!({
+                "id": "finish-whatdo",
+                "summary": "Ability to invoke `wd finish` to finish the current whatdo",
+                "priority": null,
+                "tags": ["a-tag"],
+                "branch_name": "finish-whatdo",
+                "queue": [],
+                "blocked_by": [],
+                "due": null,
+                "days_until_due": null,
+                "created": null,
+                "updated": null,
+                "extra": {"owner": "someone"},
+                "path": ["test_data", "basic-functionality"],
+                "whatdos": [{
+                    "id": "delete-whatdo",
+                    "summary": "Delete the whatdo",
+                    "priority": null,
+                    "tags": [],
+                    "branch_name": "delete-whatdo",
+                    "queue": [],
+                    "blocked_by": [],
+                    "due": null,
+                    "days_until_due": null,
+                    "created": null,
+                    "updated": null,
+                    "extra": {},
+                    "path": ["test_data", "basic-functionality", "finish-whatdo"],
+                    "whatdos": [],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let serialized = serialize_whatdo(&test_data_whatdo());
+        let parsed: serde_yaml::Value =
+            serde_yaml::from_str(&std::fs::read_to_string("./test_data/WHATDO.yaml").unwrap())
+                .unwrap();
+        assert_eq!(serialized.1, parsed);
+    }
+
+    #[test]
+    fn test_parse_includes() {
+        let parsed = parse_file(Path::new("./test_data/include_test/WHATDO.yaml")).unwrap();
+        let frontend = find_whatdo(&parsed, "frontend").unwrap();
+        assert_eq!(frontend.include, Some(PathBuf::from("frontend.yaml")));
+        assert_eq!(frontend.priority, Some(1));
+        assert_eq!(
+            find_whatdo(&parsed, "login-page").unwrap().summary(),
+            "Build the login page"
+        );
+
+        let backend = find_whatdo(&parsed, "backend").unwrap();
+        assert_eq!(backend.whatdos_dir, Some(PathBuf::from("backend")));
+        assert_eq!(
+            backend
+                .whatdos()
+                .iter()
+                .map(|wd| &wd.id)
+                .collect::<Vec<_>>(),
+            vec!["api", "db/migrations"]
+        );
+        assert_eq!(
+            find_whatdo(&parsed, "api").unwrap().tags,
+            Some(vec![String::from("api")])
+        );
+    }
+
+    #[test]
+    fn test_write_includes() {
+        let dir = TestDir::new("write-includes");
+        let path = dir.join("WHATDO.yaml");
+
+        let parsed = parse_file(Path::new("./test_data/include_test/WHATDO.yaml")).unwrap();
+        let mut touched = Touched::default();
+        write_whatdo_file(&parsed, &path, &mut touched).unwrap();
+        assert!(dir.join("frontend.yaml").exists());
+        assert!(dir.join("backend/db/migrations.yaml").exists());
+
+        let mut reparsed = parse_file(&path).unwrap();
+        reparsed.id = parsed.id.clone();
+        assert_eq!(reparsed, parsed);
+
+        let deleted = delete_whatdo(&parsed, "api");
+        let mut touched = Touched::default();
+        write_whatdo_file(&deleted, &path, &mut touched).unwrap();
+        assert!(!dir.join("backend/api.yaml").exists());
+        assert!(touched.paths().contains(&dir.join("backend/api.yaml")));
+
+        touched.roll_back().unwrap();
+        assert!(dir.join("backend/api.yaml").exists());
+        let mut rolled_back = parse_file(&path).unwrap();
+        rolled_back.id = parsed.id.clone();
+        assert_eq!(rolled_back, parsed);
+
+        // Files included by a removed whatdo are removed too
+        let touched = write_to_file(&path, &delete_whatdo(&parsed, "frontend")).unwrap();
+        assert!(!dir.join("frontend.yaml").exists());
+        assert!(touched.paths().contains(&dir.join("frontend.yaml")));
+        assert!(dir.join("backend/db/migrations.yaml").exists());
+        touched.roll_back().unwrap();
+        assert!(dir.join("frontend.yaml").exists());
+    }
+
+    #[test]
+    fn test_move_whatdo() {
+        let dir = TestDir::new("move-whatdo");
+        let path = dir.copy("./test_data/WHATDO.yaml");
+
+        let moved = move_whatdo(
+            &path,
+            "delete-whatdo",
+            Some("not-a-whatdo"),
+            None,
+            false,
+            false,
+        );
+        assert!(moved.is_err());
+        let root_id = parse_file(&path).unwrap().id;
+        let moved =
+            move_whatdo(&path, "delete-whatdo", Some(&root_id), None, false, false).unwrap();
+        assert_eq!(moved.id, "delete-whatdo");
+        let moved = move_whatdo(
+            &path,
+            "basic-functionality",
+            Some("finish-whatdo"),
+            None,
+            false,
+            false,
+        );
+        assert!(moved.is_err());
+        let moved = move_whatdo(
+            &path,
+            "delete-whatdo",
+            None,
+            Some("remove-whatdo"),
+            false,
+            false,
+        );
+        assert!(moved.is_ok());
+
+        let mut expected = test_data_whatdo();
+        let mut deleted = take_whatdo(&mut expected, "delete-whatdo").unwrap();
+        deleted.id = String::from("remove-whatdo");
+        expected.whatdos.as_mut().unwrap().push(deleted);
+        expected.queue = Some(vec![
+            String::from("read-back-whatdos"),
+            String::from("remove-whatdo"),
+        ]);
+        let mut parsed = parse_file(&path).unwrap();
+        parsed.id = expected.id.clone();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn test_apply_queue_change() {
+        let mut wd = test_data_whatdo();
+        let add = |id: &str, at| QueueChange::Add {
+            id: id.to_owned(),
+            at,
+        };
+        assert!(apply_queue_change(&mut wd, &add("not-a-whatdo", None)).is_err());
+        assert!(apply_queue_change(&mut wd, &add("test_data", None)).is_err());
+        assert!(apply_queue_change(&mut wd, &add("delete-whatdo", None)).is_err());
+        assert!(apply_queue_change(&mut wd, &add("finish-whatdo", Some(3))).is_err());
+
+        apply_queue_change(&mut wd, &add("finish-whatdo", Some(1))).unwrap();
+        assert_eq!(
+            wd.queue,
+            Some(vec![
+                String::from("read-back-whatdos"),
+                String::from("finish-whatdo"),
+                String::from("delete-whatdo"),
+            ])
+        );
+
+        let move_to = |id: &str, to| QueueChange::Move {
+            id: id.to_owned(),
+            to,
+        };
+        assert!(apply_queue_change(&mut wd, &move_to("finish-whatdo", 3)).is_err());
+        assert!(apply_queue_change(&mut wd, &move_to("finish-whatdo", 1)).is_err());
+        apply_queue_change(&mut wd, &move_to("read-back-whatdos", 2)).unwrap();
+        assert_eq!(
+            wd.queue,
+            Some(vec![
+                String::from("finish-whatdo"),
+                String::from("delete-whatdo"),
+                String::from("read-back-whatdos"),
+            ])
+        );
+
+        let remove = |id: &str| QueueChange::Remove { id: id.to_owned() };
+        assert!(apply_queue_change(&mut wd, &remove("basic-functionality")).is_err());
+        for id in ["finish-whatdo", "delete-whatdo", "read-back-whatdos"] {
+            apply_queue_change(&mut wd, &remove(id)).unwrap();
+        }
+        assert_eq!(wd.queue, None);
+    }
+
+    #[test]
+    fn test_archive() {
+        let dir = TestDir::new("archive");
+        let path = dir.copy("./test_data/WHATDO.yaml");
+
+        resolve(&path, "finish-whatdo", false, false).unwrap();
+        resolve(&path, "read-back-whatdos", false, false).unwrap();
+        assert!(dir.join("WHATDO.done.yaml").exists());
+        let archived = archive(&path).unwrap();
+        assert_eq!(
+            archived
+                .iter()
+                .map(|a| a.whatdo.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["finish-whatdo", "read-back-whatdos"]
+        );
+        assert_eq!(archived[0].parent_id, "basic-functionality");
+        assert_eq!(archived[0].branch, None);
+        assert_eq!(archived[0].whatdo.extra, finish_whatdo_extra());
+        assert!(get(&path, "delete-whatdo").unwrap().is_none());
+
+        assert!(reopen(&path, "basic-functionality", false, false).is_err());
+        // A sub-whatdo of the resolved whatdo has been added again since
+        add(
+            &path,
+            "delete-whatdo",
+            vec![],
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+        assert!(reopen(&path, "finish-whatdo", false, false).is_err());
+        delete(&path, "delete-whatdo", false, false).unwrap();
+        let (reopened, parent) = reopen(&path, "finish-whatdo", false, false).unwrap();
+        assert_eq!(parent.id, "basic-functionality");
+        assert_eq!(get(&path, "finish-whatdo").unwrap(), Some(reopened));
+        assert!(get(&path, "delete-whatdo").unwrap().is_some());
+        assert_eq!(archive(&path).unwrap(), archived[1..]);
+    }
+
+    #[test]
+    fn test_order_by_blockers() {
+        let whatdo = parse_file(Path::new("./test_data/blocking_test.yaml")).unwrap();
+        let sorted = sort_whatdos(
+            &whatdo,
+            &|_| true,
+            SortOrder::Priority,
+            &mut HashSet::new(),
+            false,
+        );
+        assert_eq!(
+            order_by_blockers(&whatdo, sorted)
+                .unwrap()
+                .iter()
+                .map(|wd| &wd.id)
+                .collect::<Vec<_>>(),
+            vec!["design", "build", "write-docs", "release"]
+        );
+
+        // Whatdos blocked by whatdos that are filtered out are never ready
+        let sorted_tags = sort_whatdos(
+            &whatdo,
+            &|wd| wd.id == "build",
+            SortOrder::Priority,
+            &mut HashSet::new(),
+            false,
+        );
+        assert_eq!(
+            order_by_blockers(&whatdo, sorted_tags)
+                .unwrap()
+                .iter()
+                .map(|wd| &wd.id)
+                .collect::<Vec<_>>(),
+            Vec::<&str>::new()
+        );
+
+        let cyclic: serde_yaml::Value = serde_yaml::from_str(
+            "whatdos: {a: {blocked_by: [b]}, b: {whatdos: {c: {blocked_by: [a]}}}}",
+        )
+        .unwrap();
+        let cyclic = parse_whatdo("root", &cyclic, Path::new(".")).unwrap();
+        let sorted = sort_whatdos(
+            &cyclic,
+            &|_| true,
+            SortOrder::Priority,
+            &mut HashSet::new(),
+            false,
+        );
+        assert_eq!(
+            order_by_blockers(&cyclic, sorted).unwrap_err().to_string(),
+            "Whatdos are blocking each other in a cycle: a -> b -> c -> a"
+        );
+    }
+
+    #[test]
+    fn test_apply_edit() {
+        let mut wd = find_whatdo(&test_data_whatdo(), "finish-whatdo").unwrap();
+        let descriptions = apply_edit(
+            &mut wd,
+            &WhatdoEdit {
+                priority: Some(3),
+                add_tags: vec![String::from("b-tag"), String::from("a-tag")],
+                remove_tags: vec![String::from("a-tag")],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            descriptions,
+            vec![
+                "set priority to 3",
+                "added tag 'b-tag'",
+                "removed tag 'a-tag'"
+            ]
+        );
+        assert_eq!(wd.priority, Some(3));
+        assert_eq!(wd.tags, Some(vec![String::from("b-tag")]));
+
+        let summary = wd.summary.clone();
+        let branch_name = wd.branch_name().clone();
+        assert_eq!(
+            apply_edit(
+                &mut wd,
+                &WhatdoEdit {
+                    summary: summary.clone(),
+                    priority: Some(3),
+                    add_tags: vec![String::from("b-tag")],
+                    remove_tags: vec![String::from("c-tag")],
+                    branch_name: Some(branch_name),
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(wd.summary, summary);
+        assert_eq!(wd.tags, Some(vec![String::from("b-tag")]));
+
+        for branch_name in ["", "-f", "not a branch"] {
+            assert!(apply_edit(
+                &mut wd,
+                &WhatdoEdit {
+                    branch_name: Some(String::from(branch_name)),
+                    ..Default::default()
+                },
+            )
+            .is_err());
         }
+        assert_eq!(
+            apply_edit(
+                &mut wd,
+                &WhatdoEdit {
+                    branch_name: Some(String::from("finish")),
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+            vec!["set branch name to 'finish'"]
+        );
+
+        assert!(apply_edit(
+            &mut wd,
+            &WhatdoEdit {
+                add_tags: vec![String::from("Not A Tag")],
+                ..Default::default()
+            },
+        )
+        .is_err());
+
+        let due = NaiveDate::from_ymd_opt(2024, 1, 31);
+        let edit_due = |wd: &mut Whatdo, due, remove_due| {
+            apply_edit(
+                wd,
+                &WhatdoEdit {
+                    due,
+                    remove_due,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            edit_due(&mut wd, due, false),
+            vec!["set due date to 2024-01-31"]
+        );
+        assert_eq!(wd.due, due);
+        assert_eq!(edit_due(&mut wd, due, false), Vec::<String>::new());
+        assert_eq!(edit_due(&mut wd, None, true), vec!["removed due date"]);
+        assert_eq!(edit_due(&mut wd, None, true), Vec::<String>::new());
+        assert_eq!(wd.due, None);
     }
 
     #[test]
-    fn test_parse_file() {
-        let parsed = parse_file(&PathBuf::from("./test_data/WHATDO.yaml"));
-        assert_eq!(parsed.unwrap(), test_data_whatdo());
+    fn test_active_id() {
+        let dir = TestDir::new("active-id");
+        let file = dir.join("WHATDO.yaml");
+
+        assert_eq!(active_id(&file).unwrap(), None);
+        set_active_id(&file, Some("some-whatdo")).unwrap();
+        assert_eq!(state_file(&file), dir.join(".whatdo/WHATDO.yaml.active"));
+        assert_eq!(active_id(&file).unwrap(), Some(String::from("some-whatdo")));
+        set_active_id(&file, None).unwrap();
+        assert_eq!(active_id(&file).unwrap(), None);
     }
 
     #[test]
-    fn test_delete_whatdo() {
-        let deleted = delete_whatdo(&test_data_whatdo(), "delete-whatdo");
+    fn test_parse_whatdo_list() {
+        let ids = |text: &str| -> Vec<(String, Vec<String>)> {
+            parse_whatdo_list(text, Path::new("."))
+                .unwrap()
+                .iter()
+                .map(|wd| {
+                    (
+                        wd.id.clone(),
+                        wd.whatdos().iter().map(|wd| wd.id.clone()).collect(),
+                    )
+                })
+                .collect()
+        };
+
         assert_eq!(
-            deleted,
+            ids("# Login\n- [ ] Fix the login bug!\n  - [ ] Write a test\n- [x] Done already\n  - [ ] Skipped\n* [ ] Ship it\n"),
+            vec![
+                (String::from("fix-the-login-bug"), vec![String::from("write-a-test")]),
+                (String::from("ship-it"), vec![]),
+            ]
+        );
+        assert_eq!(
+            ids("fix-login:\n  summary: Fix the login bug\n  whatdos:\n    write-test: Write a test\n"),
+            vec![(String::from("fix-login"), vec![String::from("write-test")])]
+        );
+        assert_eq!(
+            ids("fix-login: Fix: the login bug\n\n# Comment\nship-it\n"),
+            vec![
+                (String::from("fix-login"), vec![]),
+                (String::from("ship-it"), vec![]),
+            ]
+        );
+        assert!(parse_whatdo_list("not an id: summary", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_add_many() {
+        let dir = TestDir::new("add-many");
+        let file = dir.copy("./test_data/sort_test.yaml");
+
+        let duplicates = parse_whatdo_list(
+            "read-users-mind: Again\nnew-one: New\nnew-one: Also new\n",
+            &dir,
+        )
+        .unwrap();
+        let error = add_many(&file, duplicates, None, false, false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot add the whatdos:\nWhatdo with ID 'read-users-mind' already exists\n\
+             'new-one' is in the list more than once"
+        );
+
+        let whatdos = parse_whatdo_list(
+            "- [ ] First thing\n  - [ ] Sub thing\n- [ ] Second thing\n",
+            &dir,
+        )
+        .unwrap();
+        let (added, parent) = add_many(
+            &file,
+            whatdos,
+            Some(String::from("read-users-mind")),
+            false,
+            false,
+        )
+        .unwrap();
+        assert_eq!(added.len(), 2);
+        assert_eq!(parent.unwrap().whatdos().len(), 2);
+        let sub_thing = find_whatdo(&parse_file(&file).unwrap(), "sub-thing").unwrap();
+        assert_eq!(sub_thing.created, Some(today()));
+    }
+
+    #[test]
+    fn test_resolve_id() {
+        let dir = TestDir::new("resolve-id");
+        let file = dir.copy("./test_data/sort_test.yaml");
+
+        assert_eq!(resolve_id(&file, "finish-whatdo").unwrap(), "finish-whatdo");
+        assert!(resolve_id(&file, "@").is_err());
+
+        set_active_id(&file, Some("finish-whatdo")).unwrap();
+        assert_eq!(resolve_id(&file, "@").unwrap(), "finish-whatdo");
+        assert_eq!(resolve_id(&file, "@^").unwrap(), "basic-functionality");
+        assert_eq!(
+            resolve_id(&file, "@/delete-whatdo").unwrap(),
+            "delete-whatdo"
+        );
+        assert_eq!(
+            resolve_id(&file, "@^/read-back-whatdos").unwrap(),
+            "read-back-whatdos"
+        );
+        assert!(resolve_id(&file, "@^^").is_err());
+        assert!(resolve_id(&file, "@/read-back-whatdos").is_err());
+        assert!(resolve_id(&file, "@/").is_err());
+        assert!(resolve_id(&file, "@x").is_err());
+    }
+
+    #[test]
+    fn test_initial_whatdo_file() {
+        let content: serde_yaml::Value = serde_yaml::from_str(sample::INITIAL_WHATDO_FILE).unwrap();
+        let parsed = parse_whatdo("root", &content, Path::new(".")).unwrap();
+        assert_eq!(parsed, sample::initial_whatdo_file());
+    }
+
+    #[test]
+    fn test_sort_chronological() {
+        let whatdo = parse_file(Path::new("./test_data/chronological_test.yaml")).unwrap();
+        assert_eq!(
+            find_whatdo(&whatdo, "fix-bug").unwrap().created,
+            NaiveDate::from_ymd_opt(2024, 1, 15)
+        );
+
+        let sort = |order| {
+            sort_whatdos(&whatdo, &|_| true, order, &mut HashSet::new(), false)
+                .into_iter()
+                .map(|wd| wd.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sort(SortOrder::Chronological),
+            vec![
+                "fix-bug",
+                "write-changelog",
+                "tag-version",
+                "old-idea",
+                "write-docs",
+                "undated",
+            ]
+        );
+        assert_eq!(
+            sort(SortOrder::Priority),
+            vec![
+                "write-docs",
+                "fix-bug",
+                "old-idea",
+                "undated",
+                "tag-version",
+                "write-changelog",
+            ]
+        );
+
+        let invalid = serde_yaml::from_str("created: last tuesday").unwrap();
+        assert!(parse_whatdo("invalid", &invalid, Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_sort_due() {
+        let due_in = |id: &str, days: Option<i64>| Whatdo {
+            due: days.map(|days| today() + chrono::Duration::days(days)),
+            ..Whatdo::simple(id, None::<String>)
+        };
+        let mut whatdo = Whatdo::simple("root", None::<String>);
+        whatdo.whatdos = Some(vec![
+            due_in("undated", None),
+            due_in("due-later", Some(30)),
+            due_in("due-soon", Some(3)),
+            due_in("overdue", Some(-2)),
             Whatdo {
-                id: String::from("test_data"),
-                summary: Some(String::from(
-                    "A streamlined git-based tool for task tracking of a project",
-                )),
-                whatdos: Some(vec![Whatdo {
-                    id: String::from("basic-functionality"),
-                    summary: Some(String::from(
-                        "Implement the absolute minimum stuff for the tool to get it to be useful\nfor tracking the progress of this tool\n",
-                    )),
-                    whatdos: Some(vec![
-                        Whatdo::simple(
-                            String::from("read-back-whatdos"),
-                            Some(String::from(
-                                "Ability to invoke `wd` to list the current whatdos"
-                            )),
-                        ),
-                        Whatdo {
-                            id: String::from("finish-whatdo"),
-                            summary: Some(String::from(
-                                "Ability to invoke `wd finish` to finish the current whatdo",
-                            )),
-                            whatdos: Some(vec![]),
-                            simple_format: false,
-                            queue: None,
-                            priority: None,
-                            branch_name: None,
-                            tags: Some(vec!["a-tag".to_owned()]),
-                        },
-                    ]),
-                    queue: None,
-                    priority: Some(0),
-                    tags: None,
-                    branch_name: None,
-                    simple_format: false,
-                }]),
-                simple_format: false,
-                queue: Some(vec![String::from("read-back-whatdos")]),
-                priority: None,
-                tags: None,
-                branch_name: Some(String::from("overridden-name")),
-            }
+                priority: Some(0),
+                ..due_in("important", None)
+            },
+        ]);
+        assert_eq!(
+            find_whatdo(&whatdo, "overdue").unwrap().days_until_due(),
+            Some(-2)
+        );
+
+        let sorted = sort_whatdos(
+            &whatdo,
+            &|_| true,
+            SortOrder::Priority,
+            &mut HashSet::new(),
+            false,
         );
-        let deleted_again = delete_whatdo(&deleted, "read-back-whatdos");
         assert_eq!(
-            deleted_again,
+            sorted.iter().map(|wd| &wd.id).collect::<Vec<_>>(),
+            vec!["important", "overdue", "due-soon", "undated", "due-later"]
+        );
+
+        // Sub-whatdos that are due soon bring their parent forward
+        whatdo.whatdos.as_mut().unwrap().push(Whatdo {
+            whatdos: Some(vec![due_in("due-tomorrow", Some(1))]),
+            ..due_in("parent", None)
+        });
+        let sorted = sort_whatdos(
+            &whatdo,
+            &|_| true,
+            SortOrder::Priority,
+            &mut HashSet::new(),
+            false,
+        );
+        assert_eq!(
+            sorted.iter().map(|wd| &wd.id).collect::<Vec<_>>(),
+            vec![
+                "important",
+                "overdue",
+                "due-tomorrow",
+                "due-soon",
+                "undated",
+                "due-later"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_orders() {
+        assert_eq!("due".parse::<SortOrder>().unwrap(), SortOrder::Due);
+        assert_eq!(
+            "random:42".parse::<SortOrder>().unwrap(),
+            SortOrder::Random(42)
+        );
+        assert!("random:-1".parse::<SortOrder>().is_err());
+        assert!("alphabetical".parse::<SortOrder>().is_err());
+
+        let days_ago = |days| today() - chrono::Duration::days(days);
+        let mut whatdo = Whatdo::simple("root", None::<String>);
+        whatdo.whatdos = Some(vec![
             Whatdo {
-                id: String::from("test_data"),
-                summary: Some(String::from(
-                    "A streamlined git-based tool for task tracking of a project",
-                )),
-                whatdos: Some(vec![Whatdo {
-                    id: String::from("basic-functionality"),
-                    summary: Some(String::from(
-                        "Implement the absolute minimum stuff for the tool to get it to be useful\nfor tracking the progress of this tool\n",
-                    )),
-                    whatdos: Some(vec![Whatdo {
-                        id: String::from("finish-whatdo"),
-                        summary: Some(String::from(
-                            "Ability to invoke `wd finish` to finish the current whatdo",
-                        )),
-                        whatdos: Some(vec![]),
-                        simple_format: false,
-                        queue: None,
-                        priority: None,
-                        branch_name: None,
-                        tags: Some(vec!["a-tag".to_owned()]),
-                    },]),
-                    queue: None,
-                    priority: Some(0),
-                    tags: None,
-                    branch_name: None,
-                    simple_format: false,
-                }]),
-                simple_format: false,
-                queue: Some(vec![]),
-                priority: None,
-                tags: None,
-                branch_name: Some(String::from("overridden-name")),
-            }
+                priority: Some(1),
+                due: Some(days_ago(-40)),
+                ..Whatdo::simple("important-later", None::<String>)
+            },
+            Whatdo {
+                priority: Some(2),
+                due: Some(days_ago(-1)),
+                ..Whatdo::simple("due-tomorrow", None::<String>)
+            },
+            Whatdo {
+                priority: Some(2),
+                created: Some(days_ago(365)),
+                ..Whatdo::simple("ancient", None::<String>)
+            },
+            Whatdo::simple("unprioritized", None::<String>),
+        ]);
+        let sort = |order| {
+            sort_whatdos(&whatdo, &|_| true, order, &mut HashSet::new(), false)
+                .into_iter()
+                .map(|wd| wd.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sort(SortOrder::Due),
+            vec![
+                "due-tomorrow",
+                "important-later",
+                "ancient",
+                "unprioritized"
+            ]
+        );
+        // Scores of 15, 35, 38 and 98, since a year old counts as four weeks
+        assert_eq!(
+            sort(SortOrder::Weighted),
+            vec![
+                "due-tomorrow",
+                "important-later",
+                "ancient",
+                "unprioritized"
+            ]
+        );
+
+        let shuffled = sort(SortOrder::Random(7));
+        assert_eq!(shuffled, sort(SortOrder::Random(7)));
+        let mut ids = shuffled.clone();
+        ids.sort();
+        assert_eq!(
+            ids,
+            vec![
+                "ancient",
+                "due-tomorrow",
+                "important-later",
+                "unprioritized"
+            ]
         );
+
+        assert_eq!(default_sort_order(&whatdo).unwrap(), SortOrder::Priority);
+        whatdo.extra.insert(
+            serde_yaml::Value::from("sort"),
+            serde_yaml::Value::from("weighted"),
+        );
+        assert_eq!(default_sort_order(&whatdo).unwrap(), SortOrder::Weighted);
     }
 
     #[test]
-    fn test_serialize() {
-        let serialized = serialize_whatdo(&test_data_whatdo());
-        let parsed: serde_yaml::Value =
-            serde_yaml::from_str(&std::fs::read_to_string("./test_data/WHATDO.yaml").unwrap())
-                .unwrap();
-        assert_eq!(serialized.1, parsed);
+    fn test_query_filter() {
+        let whatdo = parse_file(Path::new("./test_data/sort_test.yaml")).unwrap();
+        let query = crate::query::parse("under:advanced_functionality and depth>=3").unwrap();
+        let sorted = sort_whatdos(
+            &whatdo,
+            &query_filter(&whatdo, Some(query)),
+            SortOrder::Priority,
+            &mut HashSet::new(),
+            false,
+        );
+        assert_eq!(
+            sorted.iter().map(|wd| &wd.id).collect::<Vec<_>>(),
+            vec!["less-fossil-fuels", "more-green-energy"]
+        );
+    }
+
+    #[test]
+    fn test_next_path() {
+        let dir = TestDir::new("next-path");
+        let path = dir.copy("./test_data/sort_test.yaml");
+
+        let next = next(&path, NextAmount::AtMost(2), None, None).unwrap();
+        assert_eq!(
+            next.iter()
+                .map(|n| (n.whatdo.id.as_str(), n.path.join("/")))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "read-back-whatdos",
+                    String::from("whatdo-test-next-path/basic-functionality")
+                ),
+                (
+                    "delete-whatdo",
+                    String::from("whatdo-test-next-path/basic-functionality/finish-whatdo")
+                ),
+            ]
+        );
+        assert_eq!(next[1].ancestor_id(1).unwrap(), "finish-whatdo");
+        assert_eq!(next[1].ancestor_id(2).unwrap(), "basic-functionality");
+        assert!(next[1].ancestor_id(3).is_err());
+        assert!(next[1].ancestor_id(0).is_err());
+    }
+
+    /// The text without the ANSI escape codes that `colored` adds
+    fn strip_colors(text: &str) -> String {
+        regex::Regex::new("\x1b\\[[0-9;]*m")
+            .unwrap()
+            .replace_all(text, "")
+            .into_owned()
+    }
+
+    #[test]
+    fn test_tree_view_depth() {
+        let root = parse_file(Path::new("./test_data/sort_test.yaml")).unwrap();
+        let view = |filter: Box<dyn Fn(&Whatdo) -> bool>, max_depth| {
+            let shown = WhatdoTreeView {
+                root: root.clone(),
+                filter,
+                transitive: true,
+                show_extra: false,
+                sort: None,
+                max_depth: Some(max_depth),
+            }
+            .to_string();
+            strip_colors(&shown)
+        };
+
+        let shown = view(Box::new(|_| true), 1);
+        assert!(shown.contains("\n>(+3 more: 2 leaves, tags todo 1)\n"));
+        assert!(
+            shown.contains("\n>(+4 more: 3 leaves, tags todo 1 todont 1, priorities P0 1 P1 1)\n")
+        );
+        assert!(!shown.contains("read-users-mind"));
+
+        let shown = view(
+            query_filter(
+                &root,
+                Some(Query::Id(String::from("advanced_functionality"))),
+            ),
+            1,
+        );
+        assert!(!shown.contains("basic-functionality"));
+        assert!(shown.contains("\n>[read-users-mind]\n"));
+        assert!(shown
+            .ends_with("\n>>(+2 more: 2 leaves, tags todo 1 todont 1, priorities P0 1 P1 1)\n"));
+
+        assert!(!view(Box::new(|_| true), 3).contains("more:"));
     }
 
     #[test]
     fn test_sort_whatdos() {
         let whatdo = parse_file(Path::new("./test_data/sort_test.yaml")).unwrap();
-        let sorted = sort_whatdos(&whatdo, &|_| true, &mut HashSet::new(), false);
+        let sorted = sort_whatdos(
+            &whatdo,
+            &|_| true,
+            SortOrder::Priority,
+            &mut HashSet::new(),
+            false,
+        );
         assert_eq!(
             sorted.iter().map(|wd| &wd.id).collect::<Vec<_>>(),
             vec![
@@ -1014,6 +4069,7 @@ for tracking the progress of this tool\n",
                     .map(|tags| tags.iter().find(|t| t.as_str() == "todo"))
                     .is_some()
             },
+            SortOrder::Priority,
             &mut HashSet::new(),
             false,
         );
@@ -1022,4 +4078,66 @@ for tracking the progress of this tool\n",
             vec!["delete-whatdo", "more-green-energy",]
         )
     }
+
+    #[test]
+    fn test_pick_remote() {
+        let remotes = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let key = "whatdo.pushRemote";
+        assert_eq!(pick_remote(key, None, &remotes(&[])).unwrap(), None);
+        assert_eq!(
+            pick_remote(key, None, &remotes(&["fork"])).unwrap(),
+            Some("fork".to_owned())
+        );
+        assert_eq!(
+            pick_remote(key, None, &remotes(&["upstream", "origin"])).unwrap(),
+            Some("origin".to_owned())
+        );
+        assert_eq!(
+            pick_remote(key, None, &remotes(&["upstream", "fork"])).unwrap(),
+            None
+        );
+        assert_eq!(
+            pick_remote(
+                key,
+                Some("upstream".to_owned()),
+                &remotes(&["upstream", "origin"])
+            )
+            .unwrap(),
+            Some("upstream".to_owned())
+        );
+        assert!(pick_remote(key, Some("upstream".to_owned()), &remotes(&["origin"])).is_err());
+    }
+
+    #[test]
+    fn test_pending_pushes() {
+        let dir = TestDir::new("pending-pushes");
+        let path = dir.join("whatdo/pending-pushes");
+
+        for branch in ["a", "b", "a", "c", "d"] {
+            queue_push(&path, branch).unwrap();
+        }
+        assert_eq!(
+            read_pending_pushes(&path).unwrap(),
+            vec!["a", "b", "c", "d"]
+        );
+
+        // The failed push and the ones after it stay queued
+        let mut attempted = Vec::new();
+        let result = flush_pending_pushes(&path, &mut |branch| {
+            attempted.push(branch.to_owned());
+            match branch {
+                "b" => Ok(false),
+                "c" => Err(Error::msg("network is down")),
+                _ => Ok(true),
+            }
+        });
+        assert!(result.is_err());
+        assert_eq!(attempted, vec!["a", "b", "c"]);
+        assert_eq!(read_pending_pushes(&path).unwrap(), vec!["c", "d"]);
+
+        let pushed = flush_pending_pushes(&path, &mut |_| Ok(true)).unwrap();
+        assert_eq!(pushed, vec!["c", "d"]);
+        assert!(!path.exists());
+        assert_eq!(read_pending_pushes(&path).unwrap(), Vec::<String>::new());
+    }
 }
@@ -1,9 +1,37 @@
-use anyhow::Result;
-use std::{
-    path::PathBuf,
-    process::Output,
-    process::{Command, ExitStatus},
-};
+use std::{fmt, path::PathBuf, process::Command, process::Output};
+
+/// A git command that couldn't be run or exited unsuccessfully
+#[derive(Debug)]
+pub struct GitError {
+    pub command: String,
+    /// None if git couldn't be run or was killed by a signal
+    pub code: Option<i32>,
+    pub stderr: String,
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "`{}` failed with exit code {}", self.command, code)?,
+            None => write!(f, "`{}` failed", self.command)?,
+        }
+        if !self.stderr.is_empty() {
+            write!(f, ": {}", self.stderr)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GitError {}
+
+pub type Result<T> = std::result::Result<T, GitError>;
+
+fn command_line(program: &str, args: &[&str]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().copied())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
 fn trimmed_stdout(output: &Output) -> String {
     String::from_utf8(output.stdout.clone())
@@ -21,7 +49,14 @@ fn run_command<'a>(program: &'a str, args: impl IntoIterator<Item = &'a str>) ->
     }
     eprint!("");
 
-    let output = Command::new(program).args(args_vec).output()?;
+    let output = Command::new(program)
+        .args(&args_vec)
+        .output()
+        .map_err(|e| GitError {
+            command: command_line(program, &args_vec),
+            code: None,
+            stderr: e.to_string(),
+        })?;
     eprint!("{}", trimmed_stdout(&output));
     eprint!("---");
     Ok(output)
@@ -29,53 +64,249 @@ fn run_command<'a>(program: &'a str, args: impl IntoIterator<Item = &'a str>) ->
 
 #[cfg(not(debug_assertions))]
 fn run_command<'a>(program: &'a str, args: impl IntoIterator<Item = &'a str>) -> Result<Output> {
-    Ok(Command::new(program).args(args).output()?)
+    let args_vec: Vec<&str> = args.into_iter().collect();
+    Command::new(program)
+        .args(&args_vec)
+        .output()
+        .map_err(|e| GitError {
+            command: command_line(program, &args_vec),
+            code: None,
+            stderr: e.to_string(),
+        })
 }
 
+fn failure(program: &str, args: &[&str], output: &Output) -> GitError {
+    GitError {
+        command: command_line(program, args),
+        code: output.status.code(),
+        stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+    }
+}
+
+/// Run the command, failing if it exits unsuccessfully. Returns its stdout
 fn simple_command<'a>(program: &'a str, args: impl IntoIterator<Item = &'a str>) -> Result<String> {
-    let output = run_command(program, args)?;
+    let args_vec: Vec<&str> = args.into_iter().collect();
+    let output = run_command(program, args_vec.iter().copied())?;
+    if !output.status.success() {
+        return Err(failure(program, &args_vec, &output));
+    }
     Ok(trimmed_stdout(&output))
 }
 
-pub fn get_root() -> Result<PathBuf> {
+/// Return the root of the current git repo, or None if not in a git repo
+pub fn get_root() -> Result<Option<PathBuf>> {
+    let output = match run_command("git", ["rev-parse", "--show-toplevel"]) {
+        Ok(output) => output,
+        // git is not installed
+        Err(_) => return Ok(None),
+    };
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    Ok(Some(PathBuf::from(trimmed_stdout(&output))))
+}
+
+/// The names of the repo's remotes
+pub fn remotes() -> Result<Vec<String>> {
+    Ok(simple_command("git", ["remote"])?
+        .lines()
+        .map(|remote| remote.to_owned())
+        .collect())
+}
+
+/// Create the branch from the start point, or from HEAD, and check it out
+pub fn checkout_new_branch(name: &str, start_point: Option<&str>) -> Result<()> {
+    simple_command(
+        "git",
+        ["checkout", "-b", name].into_iter().chain(start_point),
+    )?;
+    Ok(())
+}
+
+/// Push the branch to the remote and set it to track the remote branch
+pub fn push_branch(remote: &str, name: &str) -> Result<()> {
+    simple_command("git", ["push", "-u", remote, name])?;
+    Ok(())
+}
+
+/// Create a local branch tracking the branch of the same name on the remote,
+/// and check it out
+pub fn checkout_remote_branch(remote: &str, name: &str) -> Result<()> {
+    simple_command(
+        "git",
+        [
+            "checkout",
+            "-b",
+            name,
+            "--track",
+            &format!("{}/{}", remote, name),
+        ],
+    )?;
+    Ok(())
+}
+
+/// The absolute path of the repo's `.git` directory
+pub fn git_dir() -> Result<PathBuf> {
     Ok(PathBuf::from(simple_command(
         "git",
-        ["rev-parse", "--show-toplevel"],
+        ["rev-parse", "--absolute-git-dir"],
     )?))
 }
 
-pub fn checkout_new_branch(name: &str, push: bool) -> Result<()> {
-    simple_command("git", ["checkout", "-b", name])?;
-    if push {
-        simple_command("git", ["push", "-u", "origin", name])?;
+fn config_get(args: &[&str]) -> Result<Option<String>> {
+    let output = run_command("git", args.iter().copied())?;
+    match output.status.code() {
+        Some(0) => Ok(Some(trimmed_stdout(&output))),
+        // The option isn't set
+        Some(1) => Ok(None),
+        _ => Err(failure("git", args, &output)),
     }
+}
+
+/// The value of a boolean git config option, or None if it isn't set
+pub fn config_bool(key: &str) -> Result<Option<bool>> {
+    Ok(config_get(&["config", "--type=bool", "--get", key])?.map(|value| value == "true"))
+}
 
+/// The value of a git config option, or None if it isn't set
+pub fn config_string(key: &str) -> Result<Option<String>> {
+    config_get(&["config", "--get", key])
+}
+
+pub fn checkout(name: &str) -> Result<()> {
+    simple_command("git", ["checkout", name])?;
     Ok(())
 }
 
+/// Stash all changes, including untracked files, with the given message
+pub fn stash(message: &str) -> Result<()> {
+    simple_command(
+        "git",
+        ["stash", "push", "--include-untracked", "-m", message],
+    )?;
+    Ok(())
+}
+
+/// Return the reference of the most recent stash with the given message, like
+/// `stash@{1}`
+pub fn find_stash(message: &str) -> Result<Option<String>> {
+    Ok(
+        simple_command("git", ["stash", "list", "--format=%gd %gs"])?
+            .lines()
+            .find_map(|line| {
+                let (reference, subject) = line.split_once(' ')?;
+                // Stash subjects look like "On <branch>: <message>"
+                let (_, stash_message) = subject.split_once(": ")?;
+                (stash_message == message).then(|| reference.to_owned())
+            }),
+    )
+}
+
+pub fn stash_pop(reference: &str) -> Result<()> {
+    simple_command("git", ["stash", "pop", reference])?;
+    Ok(())
+}
+
+/// Commit all changes, including untracked files, without pushing
+pub fn commit_all(message: &str) -> Result<()> {
+    simple_command("git", ["add", "--all"])?;
+    simple_command("git", ["commit", "-m", message])?;
+    Ok(())
+}
+
+pub fn head_commit_message() -> Result<String> {
+    simple_command("git", ["log", "-1", "--format=%s"])
+}
+
+/// Undo the last commit, keeping its changes in the working tree
+pub fn uncommit() -> Result<()> {
+    simple_command("git", ["reset", "HEAD~1"])?;
+    Ok(())
+}
+
+/// The name of the current branch, or `HEAD` if it's detached
 pub fn current_branch() -> Result<String> {
+    // Unlike rev-parse, this works on a branch without any commits yet
+    let output = run_command("git", ["symbolic-ref", "--quiet", "--short", "HEAD"])?;
+    if output.status.success() {
+        return Ok(trimmed_stdout(&output));
+    }
     simple_command("git", ["rev-parse", "--abbrev-ref", "HEAD"])
 }
 
-pub fn commit(paths: impl IntoIterator<Item = PathBuf>, message: &str, push: bool) -> Result<()> {
+/// The hash of the current commit, or None if there are no commits yet
+pub fn head_commit() -> Result<Option<String>> {
+    let output = run_command("git", ["rev-parse", "--verify", "--quiet", "HEAD"])?;
+    Ok(output.status.success().then(|| trimmed_stdout(&output)))
+}
+
+/// Remove the given paths from the index, keeping their changes
+pub fn unstage(paths: impl IntoIterator<Item = PathBuf>) -> Result<()> {
+    let paths: Vec<String> = paths
+        .into_iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+    simple_command(
+        "git",
+        ["reset", "--quiet", "--"]
+            .into_iter()
+            .chain(paths.iter().map(|p| p.as_str())),
+    )?;
+    Ok(())
+}
+
+/// Commit the given paths, failing if git doesn't see any changes to them
+pub fn commit(paths: impl IntoIterator<Item = PathBuf>, message: &str) -> Result<()> {
     simple_command("git", ["reset"])?;
     for path in paths.into_iter() {
         simple_command("git", ["add", &path.to_string_lossy()])?;
     }
-    simple_command("git", ["commit", "-m", message])?;
-    if push {
-        simple_command("git", ["push"])?;
+    let args = ["commit", "-m", message];
+    let nothing_staged = run_command("git", ["diff", "--cached", "--quiet"])?
+        .status
+        .success();
+    if nothing_staged {
+        return Err(GitError {
+            command: command_line("git", &args),
+            code: None,
+            stderr: String::from("nothing was staged. Is the whatdo file ignored by git?"),
+        });
     }
+    simple_command("git", args)?;
     Ok(())
 }
 
-pub fn default_branch_name() -> Result<String> {
-    simple_command("git", ["remote", "set-head", "origin", "-a"])?;
-    Ok(String::from_iter(
-        simple_command("git", ["rev-parse", "--abbrev-ref", "origin/HEAD"])?
-            .chars()
-            .skip(7),
-    ))
+/// The repo's default branch. This is the branch the remote's HEAD points to,
+/// as last fetched. Without a remote, or if the remote's HEAD isn't known, it's
+/// the first local branch out of `init.defaultBranch`, `main` and `master`
+pub fn default_branch_name(remote: Option<&str>) -> Result<Option<String>> {
+    if let Some(remote) = remote {
+        let output = run_command(
+            "git",
+            [
+                "symbolic-ref",
+                "--quiet",
+                "--short",
+                &format!("refs/remotes/{}/HEAD", remote),
+            ],
+        )?;
+        if output.status.success() {
+            let head = trimmed_stdout(&output);
+            let prefix = format!("{}/", remote);
+            return Ok(Some(head.strip_prefix(&prefix).unwrap_or(&head).to_owned()));
+        }
+    }
+
+    let candidates = config_string("init.defaultBranch")?
+        .into_iter()
+        .chain(["main".to_owned(), "master".to_owned()]);
+    for candidate in candidates {
+        if branch_exists(&candidate)? {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
 }
 
 pub fn has_unstaged_changes() -> Result<bool> {
@@ -90,12 +321,35 @@ pub fn branch_exists(branch_name: &str) -> Result<bool> {
     Ok(output.status.success())
 }
 
-pub fn merge(target_branch_name: &str, push: bool) -> Result<()> {
+pub fn remote_branch_exists(remote: &str, branch_name: &str) -> Result<bool> {
+    let output = run_command(
+        "git",
+        [
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("refs/remotes/{}/{}", remote, branch_name),
+        ],
+    )?;
+    Ok(output.status.success())
+}
+
+/// Fast-forward the current branch to the given branch, failing if the current
+/// branch has diverged from it
+pub fn fast_forward(branch_name: &str) -> Result<()> {
+    simple_command("git", ["merge", "--ff-only", branch_name])?;
+    Ok(())
+}
+
+pub fn rename_branch(old_name: &str, new_name: &str) -> Result<()> {
+    simple_command("git", ["branch", "-m", old_name, new_name])?;
+    Ok(())
+}
+
+/// Check out the target branch and merge the current branch into it
+pub fn merge(target_branch_name: &str) -> Result<()> {
     let current_branch_name = current_branch()?;
     simple_command("git", ["checkout", target_branch_name])?;
     simple_command("git", ["merge", &current_branch_name])?;
-    if push {
-        simple_command("git", ["push"])?;
-    }
     Ok(())
 }
@@ -1,9 +1,16 @@
-use core::NextAmount;
+use core::{NextAmount, NextWhatdo, QueueChange, SortOrder};
+
+use std::path::{Path, PathBuf};
 
 use anyhow::{Error, Result};
-use clap::{Parser, Subcommand};
+use chrono::NaiveDate;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use colored::Colorize;
+use serde_json::json;
 
+use crate::completions::CompletionKind;
 use crate::core::{Whatdo, WhatdoTreeView};
+use crate::query::{Comparison, Query};
 
 extern crate clap;
 extern crate colored;
@@ -15,27 +22,120 @@ extern crate serde_yaml;
 extern crate sqlite;
 extern crate yaml_rust;
 
+mod completions;
 mod core;
 mod git;
+mod query;
 mod sample;
+mod yaml_edit;
+
+#[derive(Subcommand, Debug, Clone)]
+enum QueueCommand {
+    #[command(about = "Add a whatdo to a queue")]
+    Add {
+        #[arg(help = "ID of the whatdo to add")]
+        id: String,
+
+        #[arg(
+            long,
+            help = "Position in the queue, starting from 0. Default is the end"
+        )]
+        at: Option<usize>,
+
+        #[arg(
+            long = "in",
+            help = "ID of the whatdo owning the queue. Default is the root whatdo"
+        )]
+        owner: Option<String>,
+
+        #[arg(long, help = "Don't commit the change to the git repo, if applicable")]
+        no_commit: bool,
+    },
+
+    #[command(about = "Remove a whatdo from a queue")]
+    Rm {
+        #[arg(help = "ID of the whatdo to remove")]
+        id: String,
+
+        #[arg(
+            long = "in",
+            help = "ID of the whatdo owning the queue. Default is the root whatdo"
+        )]
+        owner: Option<String>,
+
+        #[arg(long, help = "Don't commit the change to the git repo, if applicable")]
+        no_commit: bool,
+    },
+
+    #[command(about = "Move a whatdo to a different position in a queue")]
+    Mv {
+        #[arg(help = "ID of the whatdo to move")]
+        id: String,
+
+        #[arg(help = "New position in the queue, starting from 0")]
+        to: usize,
+
+        #[arg(
+            long = "in",
+            help = "ID of the whatdo owning the queue. Default is the root whatdo"
+        )]
+        owner: Option<String>,
+
+        #[arg(long, help = "Don't commit the change to the git repo, if applicable")]
+        no_commit: bool,
+    },
+
+    #[command(about = "Show the whatdos in a queue")]
+    Ls {
+        #[arg(
+            long = "in",
+            help = "ID of the whatdo owning the queue. Default is the root whatdo"
+        )]
+        owner: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum DoneCommand {
+    #[command(about = "Show the resolved whatdos, oldest first")]
+    Ls {},
+}
 
 #[derive(Subcommand, Debug, Clone)]
 enum Command {
-    #[command(about = "Initialize the current git repo with a whatdo file")]
+    #[command(about = "Initialize the current git repo or directory with a whatdo file")]
     Init {},
 
     #[command(about = "Display the active whatdo and the next few to do")]
     Status {},
 
+    #[command(about = "Push the branches that changed while offline")]
+    Sync {},
+
     #[command(about = "Output the path to the current whatdo file")]
     Path {},
 
+    #[command(about = "Output a shell completion script, e.g. `source <(wd completions bash)`")]
+    Completions { shell: clap_complete::Shell },
+
+    #[command(name = "__complete", hide = true)]
+    Complete { kind: CompletionKind },
+
     #[command(about = "Add a new whatdo")]
     Add {
         #[arg(
+            required_unless_present = "from",
             help = "Short, unique, slug-style identifier for the whatdo. This will be used as the branch name if using `wd start`"
         )]
-        id: String,
+        id: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["id", "tags", "summary", "priority", "branch_name", "due", "start"],
+            help = "Add all the whatdos in the file, or stdin if '-', at once. The file can be a markdown checklist, YAML like the whatdos in a whatdo file, or `id: summary` lines"
+        )]
+        from: Option<PathBuf>,
 
         #[arg(
             short,
@@ -68,6 +168,9 @@ enum Command {
         #[arg(short = 'r', long, help = "ID of the parent whatdo, if any")]
         parent: Option<String>,
 
+        #[arg(long, help = "Date the whatdo is due, like 2024-01-31")]
+        due: Option<NaiveDate>,
+
         #[arg(long, help = "Automatically start the whatdo")]
         start: bool,
 
@@ -75,6 +178,82 @@ enum Command {
         no_commit: bool,
     },
 
+    #[command(about = "Change the fields of an existing whatdo")]
+    Edit {
+        #[arg(help = "ID of the whatdo to edit")]
+        id: String,
+
+        #[arg(
+            short = 'm',
+            long,
+            help = "New freetext description of what this whatdo is about"
+        )]
+        summary: Option<String>,
+
+        #[arg(short, long, help = "New integer priority of the whatdo")]
+        priority: Option<i64>,
+
+        #[arg(
+            short = 't',
+            long,
+            value_delimiter = ',',
+            help = "Comma-separated list of tags to add to the whatdo"
+        )]
+        add_tags: Vec<String>,
+
+        #[arg(
+            short = 'T',
+            long,
+            value_delimiter = ',',
+            help = "Comma-separated list of tags to remove from the whatdo"
+        )]
+        remove_tags: Vec<String>,
+
+        #[arg(
+            short,
+            long,
+            help = "New name of the git branch for the whatdo. An existing branch for the whatdo is renamed"
+        )]
+        branch_name: Option<String>,
+
+        #[arg(long, help = "New date the whatdo is due, like 2024-01-31")]
+        due: Option<NaiveDate>,
+
+        #[arg(
+            long,
+            conflicts_with = "due",
+            help = "Remove the due date of the whatdo"
+        )]
+        no_due: bool,
+
+        #[arg(long, help = "Don't commit the change to the git repo, if applicable")]
+        no_commit: bool,
+    },
+
+    #[command(about = "Move a whatdo and its sub-whatdos under a different parent, or rename it")]
+    Mv {
+        #[arg(help = "ID of the whatdo to move")]
+        id: String,
+
+        #[arg(short = 'r', long, help = "ID of the new parent whatdo")]
+        parent: Option<String>,
+
+        #[arg(
+            long = "id",
+            help = "New ID of the whatdo. A branch for the whatdo is renamed to match"
+        )]
+        new_id: Option<String>,
+
+        #[arg(long, help = "Don't commit the change to the git repo, if applicable")]
+        no_commit: bool,
+    },
+
+    #[command(about = "Manage the queue of whatdos to do first")]
+    Queue {
+        #[clap(subcommand)]
+        cmd: QueueCommand,
+    },
+
     #[command(about = "Show all whatdos or a specific whatdo")]
     Show {
         #[arg(help = "ID of the whatdo to show")]
@@ -93,6 +272,29 @@ enum Command {
             help = "Comma-separated list of priorties. Only show whatdos that have one of the given priorities"
         )]
         priorities: Vec<i64>,
+
+        #[arg(long, help = "Also show any extra fields of each whatdo")]
+        extra: bool,
+
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u64).range(1..),
+            help = "Only show this many levels of whatdos, and summarize the whatdos below them"
+        )]
+        depth: Option<u64>,
+
+        #[arg(
+            short,
+            long,
+            help = "Only show whatdos matching the query, e.g. 'tag:ui and not tag:optional and priority<=2'. See the README for the syntax"
+        )]
+        query: Option<String>,
+
+        #[arg(
+            long,
+            help = "Order sibling whatdos by 'priority', 'chronological', 'due', 'weighted', 'random' or 'random:<seed>' instead of their order in the file"
+        )]
+        sort: Option<SortOrder>,
     },
 
     #[command(about = "Show the next whatdo in the queue")]
@@ -103,6 +305,13 @@ enum Command {
         )]
         start: bool,
 
+        #[clap(
+            long,
+            value_name = "N",
+            help = "Start the Nth ancestor of the next whatdo instead, where 1 is its parent. Incompatible with --all and -n"
+        )]
+        start_parent: Option<usize>,
+
         #[clap(long, help = "Show all next whatdos")]
         all: bool,
 
@@ -122,6 +331,19 @@ enum Command {
             help = "Comma-separated list of priorties. Only include whatdos that have one of the given priorities"
         )]
         priorities: Vec<i64>,
+
+        #[arg(
+            short,
+            long,
+            help = "Only include whatdos with an ancestor matching the query, e.g. 'tag:ui and priority<=2'. See the README for the syntax"
+        )]
+        query: Option<String>,
+
+        #[arg(
+            long,
+            help = "Order sibling whatdos by 'priority', 'chronological', 'due', 'weighted', 'random' or 'random:<seed>'. Default is the 'sort' set in the whatdo file, or 'priority'"
+        )]
+        sort: Option<SortOrder>,
     },
 
     #[command(about = "Alias for 'show'")]
@@ -142,6 +364,29 @@ enum Command {
             help = "Comma-separated list of priorties. Only show whatdos that have one of the given priorities"
         )]
         priorities: Vec<i64>,
+
+        #[arg(long, help = "Also show any extra fields of each whatdo")]
+        extra: bool,
+
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u64).range(1..),
+            help = "Only show this many levels of whatdos, and summarize the whatdos below them"
+        )]
+        depth: Option<u64>,
+
+        #[arg(
+            short,
+            long,
+            help = "Only show whatdos matching the query, e.g. 'tag:ui and not tag:optional and priority<=2'. See the README for the syntax"
+        )]
+        query: Option<String>,
+
+        #[arg(
+            long,
+            help = "Order sibling whatdos by 'priority', 'chronological', 'due', 'weighted', 'random' or 'random:<seed>' instead of their order in the file"
+        )]
+        sort: Option<SortOrder>,
     },
 
     #[command(about = "Alias for 'delete'")]
@@ -168,8 +413,47 @@ enum Command {
         no_commit: bool,
     },
 
-    #[command(about = "Start a whatdo by checking out a git branch")]
-    Start { id: String },
+    #[command(about = "Browse the archive of resolved whatdos")]
+    Done {
+        #[clap(subcommand)]
+        cmd: DoneCommand,
+    },
+
+    #[command(about = "Restore a resolved whatdo from the archive to its original parent")]
+    Reopen {
+        #[arg(help = "ID of the resolved whatdo")]
+        id: String,
+
+        #[arg(long, help = "Don't commit the change to the git repo, if applicable")]
+        no_commit: bool,
+    },
+
+    #[command(
+        about = "Start a whatdo by checking out a git branch, or resume it if the branch already exists"
+    )]
+    Start {
+        id: String,
+
+        #[arg(
+            long,
+            help = "When resuming, fast-forward the branch to the branch of the parent whatdo"
+        )]
+        fast_forward: bool,
+    },
+
+    #[command(
+        about = "Set aside changes to the current whatdo and switch to another, bringing back any changes set aside on its branch"
+    )]
+    Switch {
+        #[arg(help = "ID of the whatdo to switch to")]
+        id: String,
+
+        #[arg(
+            long,
+            help = "Set aside changes in a commit, which is undone when switching back, instead of a stash"
+        )]
+        wip: bool,
+    },
 
     #[command(
         about = "Finish the current whatdo by resolving it then merging with the parent branch"
@@ -183,32 +467,123 @@ enum Command {
     },
 }
 
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     #[clap(subcommand)]
     cmd: Option<Command>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "WHATDO_FILE",
+        help = "Path to the whatdo file to use. Default is WHATDO.yaml at the root of the current git repo, or the closest one in a parent directory outside of git"
+    )]
+    file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Output format. JSON output includes the full whatdos, with the IDs of their ancestors and their sub-whatdos"
+    )]
+    format: OutputFormat,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Don't push to the remote. Changed branches are pushed later by `wd sync`. Also enabled by `git config whatdo.offline true`"
+    )]
+    no_push: bool,
+}
+
+/// Combine the filter options into a single query. Whatdos must have one of the
+/// tags, one of the priorities and match the query. IDs in the query can be
+/// relative to the current whatdo
+fn build_query(
+    file: &Path,
+    tags: Vec<String>,
+    priorities: Vec<i64>,
+    query: Option<String>,
+) -> Result<Option<Query>> {
+    let any_tag = tags.into_iter().map(Query::Tag).reduce(Query::or);
+    let any_priority = priorities
+        .into_iter()
+        .map(|p| Query::Priority(Comparison::Eq, p))
+        .reduce(Query::or);
+    let query = query
+        .map(|q| query::parse(&q)?.map_ids(&|id| core::resolve_id(file, id)))
+        .transpose()?;
+    Ok([any_tag, any_priority, query]
+        .into_iter()
+        .flatten()
+        .reduce(Query::and))
+}
+
+fn print_json(value: serde_json::Value) {
+    println!("{}", serde_json::to_string_pretty(&value).unwrap());
+}
+
+fn get_root(file: &Path) -> Result<Whatdo> {
+    core::root(file)?.ok_or(Error::msg(
+        "No whatdo file found. Try `wd init` to create one",
+    ))
 }
 
+fn get(file: &Path, id: &str) -> Result<Whatdo> {
+    let id = core::resolve_id(file, id)?;
+    core::get(file, &id)?.ok_or(Error::msg(format!("Whatdo '{}' not found", id)))
+}
+
+#[allow(clippy::too_many_arguments)]
 fn add(
+    file: &Path,
+    format: OutputFormat,
     id: String,
     tags: Vec<String>,
     summary: Option<String>,
     priority: Option<i64>,
     branch_name: Option<String>,
     parent: Option<String>,
+    due: Option<NaiveDate>,
     start: bool,
     no_commit: bool,
+    push: bool,
 ) -> Result<()> {
     let (new, parent) = core::add(
+        file,
         &id,
         tags,
-        summary.as_ref().map(|s| s.as_str()),
+        summary.as_deref(),
         priority,
         branch_name,
         parent,
+        due,
         !no_commit,
+        push,
     )?;
+    if start {
+        core::start(file, &new, false, push)?;
+    }
+
+    if format == OutputFormat::Json {
+        let root = get_root(file)?;
+        print_json(json!({
+            "added": core::whatdo_json(&root, &new),
+            "parent": parent.map(|parent| core::whatdo_json(&root, &parent)),
+            "started": start,
+        }));
+        return Ok(());
+    }
+
     println!("Added:");
     println!("{}", new);
 
@@ -219,7 +594,6 @@ fn add(
     }
 
     if start {
-        core::start(&new)?;
         println!("");
         println!("Started:");
         println!("{}", new);
@@ -228,62 +602,283 @@ fn add(
     Ok(())
 }
 
-fn show(id: Option<String>, tags: Vec<String>, priorities: Vec<i64>) -> Result<()> {
-    if id.is_some() && (tags.len() > 0 || priorities.len() > 0) {
+fn add_from(
+    file: &Path,
+    format: OutputFormat,
+    from: &Path,
+    parent: Option<String>,
+    no_commit: bool,
+    push: bool,
+) -> Result<()> {
+    let text = if from == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        std::fs::read_to_string(from)
+            .map_err(|e| Error::msg(format!("Cannot read '{}': {}", from.display(), e)))?
+    };
+    let dir = file.parent().unwrap_or(Path::new("."));
+    let whatdos = core::parse_whatdo_list(&text, dir)?;
+    let (added, parent) = core::add_many(file, whatdos, parent, !no_commit, push)?;
+
+    if format == OutputFormat::Json {
+        let root = get_root(file)?;
+        print_json(json!({
+            "added": added
+                .iter()
+                .map(|wd| core::whatdo_json(&root, wd))
+                .collect::<Vec<_>>(),
+            "parent": parent.map(|parent| core::whatdo_json(&root, &parent)),
+        }));
+        return Ok(());
+    }
+
+    println!("Added:");
+    for wd in added {
+        println!("{}", wd);
+    }
+
+    if let Some(parent) = parent {
+        println!();
+        println!("Parent:");
+        println!("{}", parent);
+    }
+
+    Ok(())
+}
+
+fn edit(
+    file: &Path,
+    format: OutputFormat,
+    id: &str,
+    changes: core::WhatdoEdit,
+    no_commit: bool,
+    push: bool,
+) -> Result<()> {
+    let wd = core::edit(
+        file,
+        &core::resolve_id(file, id)?,
+        &changes,
+        !no_commit,
+        push,
+    )?;
+    if format == OutputFormat::Json {
+        print_json(json!({ "edited": core::whatdo_json(&get_root(file)?, &wd) }));
+        return Ok(());
+    }
+
+    println!("Edited:");
+    println!("{}", wd);
+    Ok(())
+}
+
+fn mv(
+    file: &Path,
+    format: OutputFormat,
+    id: &str,
+    parent: Option<String>,
+    new_id: Option<String>,
+    no_commit: bool,
+    push: bool,
+) -> Result<()> {
+    let id = core::resolve_id(file, id)?;
+    let parent = parent
+        .map(|parent| core::resolve_id(file, &parent))
+        .transpose()?;
+    let wd = core::move_whatdo(
+        file,
+        &id,
+        parent.as_deref(),
+        new_id.as_deref(),
+        !no_commit,
+        push,
+    )?;
+    if format == OutputFormat::Json {
+        print_json(json!({ "moved": core::whatdo_json(&get_root(file)?, &wd) }));
+        return Ok(());
+    }
+
+    println!("Moved:");
+    println!("{}", wd);
+    Ok(())
+}
+
+fn queue(file: &Path, format: OutputFormat, cmd: QueueCommand, push: bool) -> Result<()> {
+    let resolve_owner = |owner: Option<String>| -> Result<Option<String>> {
+        owner
+            .map(|owner| core::resolve_id(file, &owner))
+            .transpose()
+    };
+    let queue = match cmd {
+        QueueCommand::Ls { owner } => core::queue(file, resolve_owner(owner)?.as_deref())?,
+        QueueCommand::Add {
+            id,
+            at,
+            owner,
+            no_commit,
+        } => core::edit_queue(
+            file,
+            resolve_owner(owner)?.as_deref(),
+            &QueueChange::Add {
+                id: core::resolve_id(file, &id)?,
+                at,
+            },
+            !no_commit,
+            push,
+        )?,
+        QueueCommand::Rm {
+            id,
+            owner,
+            no_commit,
+        } => core::edit_queue(
+            file,
+            resolve_owner(owner)?.as_deref(),
+            &QueueChange::Remove {
+                id: core::resolve_id(file, &id)?,
+            },
+            !no_commit,
+            push,
+        )?,
+        QueueCommand::Mv {
+            id,
+            to,
+            owner,
+            no_commit,
+        } => core::edit_queue(
+            file,
+            resolve_owner(owner)?.as_deref(),
+            &QueueChange::Move {
+                id: core::resolve_id(file, &id)?,
+                to,
+            },
+            !no_commit,
+            push,
+        )?,
+    };
+
+    if format == OutputFormat::Json {
+        let root = get_root(file)?;
+        print_json(json!({
+            "owner": core::whatdo_json(&root, &queue.owner),
+            "items": queue
+                .items
+                .iter()
+                .map(|(id, wd)| json!({
+                    "id": id,
+                    "whatdo": wd.as_ref().map(|wd| core::whatdo_json(&root, wd)),
+                }))
+                .collect::<Vec<_>>(),
+        }));
+        return Ok(());
+    }
+
+    print!("{}", queue);
+    Ok(())
+}
+
+/// Whatdos that satisfy the filter and don't have an ancestor that does
+fn topmost_matches(wd: &Whatdo, filter: &dyn Fn(&Whatdo) -> bool) -> Vec<Whatdo> {
+    if filter(wd) {
+        return vec![wd.clone()];
+    }
+    wd.whatdos()
+        .iter()
+        .flat_map(|subwhatdo| topmost_matches(subwhatdo, filter))
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn show(
+    file: &Path,
+    format: OutputFormat,
+    id: Option<String>,
+    tags: Vec<String>,
+    priorities: Vec<i64>,
+    query: Option<String>,
+    extra: bool,
+    depth: Option<u64>,
+    sort: Option<SortOrder>,
+) -> Result<()> {
+    if id.is_some() && (tags.len() > 0 || priorities.len() > 0 || query.is_some()) {
+        return Err(Error::msg(
+            "Cannot specify both an ID and tags, priorities or a query",
+        ));
+    }
+
+    if format == OutputFormat::Json && (depth.is_some() || sort.is_some()) {
         return Err(Error::msg(
-            "Cannot specify both an ID and tags or priorities",
+            "Cannot specify --depth or --sort with --format json",
         ));
     }
 
-    let root = match core::root()? {
-        None => return Err(Error::msg("No whatdo file found")),
-        Some(root) => root,
-    };
+    let root = get_root(file)?;
 
-    if let Some(id) = id {
-        let wd = core::get(&id)?;
-        match wd {
-            None => eprintln!("Not found"),
-            Some(_) => {
-                print!(
-                    "{}",
-                    WhatdoTreeView {
-                        root,
-                        filter: Box::new(move |w| w.id == id),
-                        transitive: true
-                    }
-                )
-            }
-        }
+    let query = if let Some(id) = id {
+        Some(Query::Id(get(file, &id)?.id))
     } else {
-        print!(
-            "{}",
-            WhatdoTreeView {
-                root,
-                filter: Box::new(move |w: &Whatdo| {
-                    (tags.len() == 0
-                        || (w.tags.is_some()
-                            && w.tags.as_ref().unwrap().iter().any(|t| tags.contains(t))))
-                        && (priorities.len() == 0
-                            || (w.priority.is_some() && priorities.contains(&w.priority.unwrap())))
-                }),
-                transitive: true
-            }
-        )
+        build_query(file, tags, priorities, query)?
+    };
+    let filter = core::query_filter(&root, query);
+
+    if format == OutputFormat::Json {
+        print_json(json!({
+            "whatdos": topmost_matches(&root, &*filter)
+                .iter()
+                .map(|wd| core::whatdo_json(&root, wd))
+                .collect::<Vec<_>>(),
+        }));
+        return Ok(());
     }
 
+    print!(
+        "{}",
+        WhatdoTreeView {
+            root,
+            filter,
+            transitive: true,
+            show_extra: extra,
+            sort,
+            max_depth: depth.map(|depth| depth as usize),
+        }
+    );
+
     Ok(())
 }
 
+/// Print the whatdos, with the path to them whenever it changes
+fn print_next_whatdos(whatdos: &[NextWhatdo]) {
+    let mut previous_breadcrumb = None;
+    for next in whatdos {
+        let breadcrumb = next.breadcrumb();
+        if let Some(path) = breadcrumb
+            .as_ref()
+            .filter(|b| Some(*b) != previous_breadcrumb.as_ref())
+        {
+            println!("{}", path.dimmed());
+        }
+        println!("{}", next.whatdo);
+        previous_breadcrumb = breadcrumb;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn next(
+    file: &Path,
+    format: OutputFormat,
     start: bool,
+    start_parent: Option<usize>,
     all: bool,
     n: Option<usize>,
     tags: Vec<String>,
     priorities: Vec<i64>,
+    query: Option<String>,
+    sort: Option<SortOrder>,
+    push: bool,
 ) -> Result<()> {
+    let start = start || start_parent.is_some();
     if start && (all || n.filter(|n| n != &1).is_some()) {
-        return Err(Error::msg("Cannot specify both --start and --all or -n"));
+        return Err(Error::msg(
+            "Cannot specify both --start or --start-parent and --all or -n",
+        ));
     }
 
     let next_amount = if all {
@@ -292,89 +887,230 @@ fn next(
         NextAmount::AtMost(n.unwrap_or(1usize))
     };
 
-    let whatdos = core::next(next_amount, tags, priorities)?;
+    let whatdos = core::next(
+        file,
+        next_amount,
+        build_query(file, tags, priorities, query)?,
+        sort,
+    )?;
+    let root = get_root(file)?;
     if start {
-        if whatdos.len() == 0 {
-            println!("No whatdos to start");
-        } else {
-            let wd = &whatdos[0];
-            core::start(wd)?;
-            println!("Started:");
-            println!("{}", wd);
+        let started = match (whatdos.first(), start_parent) {
+            (None, _) => None,
+            (Some(next), None) => Some(next.whatdo.clone()),
+            (Some(next), Some(n)) => Some(get(file, next.ancestor_id(n)?)?),
+        };
+        if let Some(wd) = &started {
+            core::start(file, wd, false, push)?;
         }
-    } else {
-        for wd in whatdos {
-            println!("{}", wd);
+
+        match (format, &started) {
+            (OutputFormat::Json, _) => print_json(json!({
+                "started": started.as_ref().map(|wd| core::whatdo_json(&root, wd)),
+            })),
+            (OutputFormat::Text, None) => println!("No whatdos to start"),
+            (OutputFormat::Text, Some(wd)) => {
+                println!("Started:");
+                println!("{}", wd);
+            }
         }
+    } else if format == OutputFormat::Json {
+        print_json(json!({
+            "whatdos": whatdos
+                .iter()
+                .map(|next| core::whatdo_json(&root, &next.whatdo))
+                .collect::<Vec<_>>(),
+        }));
+    } else {
+        print_next_whatdos(&whatdos);
     }
 
     Ok(())
 }
 
-fn start(id: &str) -> Result<()> {
-    let wd = core::get(id)?;
-    match wd {
-        None => eprintln!("Not found"),
-        Some(wd) => {
-            core::start(&wd)?;
-            println!("Started:");
-            println!("{}", wd);
-        }
+fn start(
+    file: &Path,
+    format: OutputFormat,
+    id: &str,
+    fast_forward: bool,
+    push: bool,
+) -> Result<()> {
+    let wd = get(file, id)?;
+    let resumed = core::start(file, &wd, fast_forward, push)?;
+    if format == OutputFormat::Json {
+        print_json(json!({
+            "started": core::whatdo_json(&get_root(file)?, &wd),
+            "resumed": resumed,
+        }));
+        return Ok(());
     }
+
+    println!("{}", if resumed { "Resumed:" } else { "Started:" });
+    println!("{}", wd);
     Ok(())
 }
 
-fn finish(no_commit: bool, no_merge: bool) -> Result<()> {
-    let wd = core::current()?;
-    match wd {
-        None => eprintln!("No current whatdo"),
-        Some(wd) => {
-            core::finish(!no_commit, !no_merge)?;
-            println!("Finished:");
-            println!("{}", wd);
-            println!("");
-            println!("Congratulations!")
-        }
+fn switch(file: &Path, format: OutputFormat, id: &str, wip: bool, push: bool) -> Result<()> {
+    let wd = get(file, id)?;
+    let parking = if wip {
+        core::Parking::WipCommit
+    } else {
+        core::Parking::Stash
+    };
+    let switch = core::switch(file, &wd, parking, push)?;
+    let parking_name = |parking: core::Parking| match parking {
+        core::Parking::Stash => "stash",
+        core::Parking::WipCommit => "commit",
+    };
+
+    if format == OutputFormat::Json {
+        print_json(json!({
+            "switched": core::whatdo_json(&get_root(file)?, &wd),
+            "parked": switch.parked.map(parking_name),
+            "restored": switch.restored.map(parking_name),
+        }));
+        return Ok(());
+    }
+
+    if let Some(parked) = switch.parked {
+        println!("Set aside changes in a {}", parking_name(parked));
+    }
+    println!("Switched to:");
+    println!("{}", wd);
+    if let Some(restored) = switch.restored {
+        println!("Brought back changes from a {}", parking_name(restored));
     }
     Ok(())
 }
 
-fn delete(id: &str, no_commit: bool) -> Result<()> {
-    let wd = core::get(id)?;
-    match wd {
-        None => eprintln!("Not found"),
-        Some(wd) => {
-            core::delete(id, !no_commit)?;
-            println!("Deleted:");
-            println!("{}", wd);
+fn finish(
+    file: &Path,
+    format: OutputFormat,
+    no_commit: bool,
+    no_merge: bool,
+    push: bool,
+) -> Result<()> {
+    let root = get_root(file)?;
+    let wd = core::current(file)?.ok_or(Error::msg("No current whatdo"))?;
+    core::finish(file, !no_commit, !no_merge, push)?;
+    if format == OutputFormat::Json {
+        print_json(json!({ "finished": core::whatdo_json(&root, &wd) }));
+        return Ok(());
+    }
+
+    println!("Finished:");
+    println!("{}", wd);
+    println!("");
+    println!("Congratulations!");
+    Ok(())
+}
+
+fn delete(file: &Path, format: OutputFormat, id: &str, no_commit: bool, push: bool) -> Result<()> {
+    let root = get_root(file)?;
+    let wd = get(file, id)?;
+    core::delete(file, &wd.id, !no_commit, push)?;
+    if format == OutputFormat::Json {
+        print_json(json!({ "deleted": core::whatdo_json(&root, &wd) }));
+        return Ok(());
+    }
+
+    println!("Deleted:");
+    println!("{}", wd);
+    Ok(())
+}
+
+fn resolve(file: &Path, format: OutputFormat, id: &str, no_commit: bool, push: bool) -> Result<()> {
+    let root = get_root(file)?;
+    let wd = get(file, id)?;
+    core::resolve(file, &wd.id, !no_commit, push)?;
+    if format == OutputFormat::Json {
+        print_json(json!({ "resolved": core::whatdo_json(&root, &wd) }));
+        return Ok(());
+    }
+
+    println!("Resolved:");
+    println!("{}", wd);
+    println!("");
+    println!("Well done!");
+    Ok(())
+}
+
+fn done(file: &Path, format: OutputFormat, cmd: DoneCommand) -> Result<()> {
+    match cmd {
+        DoneCommand::Ls {} => {
+            let archive = core::archive(file)?;
+            if format == OutputFormat::Json {
+                print_json(json!({
+                    "resolved": archive.iter().map(|a| a.to_json()).collect::<Vec<_>>(),
+                }));
+                return Ok(());
+            }
+
+            if archive.is_empty() {
+                println!("No resolved whatdos");
+            }
+            for archived in archive {
+                println!("{}", archived);
+            }
         }
     }
     Ok(())
 }
 
-fn resolve(id: &str, no_commit: bool) -> Result<()> {
-    let wd = core::get(id)?;
-    match wd {
-        None => eprintln!("Not found"),
-        Some(wd) => {
-            core::resolve(&wd.id, !no_commit)?;
-            println!("Resolved:");
-            println!("{}", wd);
-            println!("");
-            println!("Well done!");
+fn reopen(file: &Path, format: OutputFormat, id: &str, no_commit: bool, push: bool) -> Result<()> {
+    let (wd, parent) = core::reopen(file, id, !no_commit, push)?;
+    if format == OutputFormat::Json {
+        let root = get_root(file)?;
+        print_json(json!({
+            "reopened": core::whatdo_json(&root, &wd),
+            "parent": core::whatdo_json(&root, &parent),
+        }));
+        return Ok(());
+    }
+
+    println!("Reopened:");
+    println!("{}", wd);
+    println!();
+    println!("Parent:");
+    println!("{}", parent);
+    Ok(())
+}
+
+/// Print the candidates for shell completion, one per line
+fn complete(file: &Path, kind: CompletionKind) -> Result<()> {
+    if let Some(root) = core::root(file)? {
+        for candidate in completions::candidates(&root, kind) {
+            println!("{}", candidate);
         }
     }
     Ok(())
 }
 
-fn status() -> Result<()> {
-    let root = core::root()?;
-    if root.is_none() {
-        println!("No whatdo file found. Try `wd init` to create one in the current repo");
+fn status(file: &Path, format: OutputFormat) -> Result<()> {
+    let root = match (core::root(file)?, format) {
+        (Some(root), _) => root,
+        (None, OutputFormat::Json) => get_root(file)?,
+        (None, OutputFormat::Text) => {
+            println!("No whatdo file found. Try `wd init` to create one");
+            return Ok(());
+        }
+    };
+
+    let wd = core::current(file)?;
+    let wds = core::next(file, NextAmount::AtMost(10), None, None)?;
+    let pending_pushes = core::pending_pushes(file)?;
+    if format == OutputFormat::Json {
+        print_json(json!({
+            "active": wd.map(|wd| core::whatdo_json(&root, &wd)),
+            "next": wds
+                .iter()
+                .map(|next| core::whatdo_json(&root, &next.whatdo))
+                .collect::<Vec<_>>(),
+            "pending_pushes": pending_pushes,
+        }));
         return Ok(());
     }
 
-    let wd = core::current()?;
     match wd {
         None => println!("No active whatdo"),
         Some(wd) => {
@@ -385,46 +1121,97 @@ fn status() -> Result<()> {
 
     println!("");
 
-    let wds = core::next(NextAmount::AtMost(10), vec![], vec![])?;
     if wds.len() > 0 {
         println!("Next few whatdos:");
-        for wd in wds {
-            println!("{}", wd);
-        }
+        print_next_whatdos(&wds);
     } else {
         println!("No whatdos coming up. Add some with `wd add`!");
     }
 
+    if !pending_pushes.is_empty() {
+        println!();
+        println!(
+            "{} branch{} waiting to be pushed. Run `wd sync` to push them",
+            pending_pushes.len(),
+            if pending_pushes.len() == 1 {
+                " is"
+            } else {
+                "es are"
+            }
+        );
+    }
+
+    Ok(())
+}
+
+fn sync(file: &Path, format: OutputFormat) -> Result<()> {
+    let pushed = core::sync(file)?;
+    if format == OutputFormat::Json {
+        print_json(json!({ "pushed": pushed }));
+        return Ok(());
+    }
+
+    if pushed.is_empty() {
+        println!("Nothing to push");
+        return Ok(());
+    }
+
+    println!("Pushed:");
+    for branch in pushed {
+        println!("{}", branch);
+    }
     Ok(())
 }
 
-fn init() -> Result<()> {
-    let path = core::init()?;
+fn init(file: &Path, format: OutputFormat) -> Result<()> {
+    core::init(file)?;
+    if format == OutputFormat::Json {
+        print_json(json!({ "file": file }));
+        return Ok(());
+    }
+
     println!("Whatdo file initialized at:");
-    println!("{}", path.to_string_lossy());
+    println!("{}", file.to_string_lossy());
     println!("");
     println!("Run `wd` to get started");
     Ok(())
 }
 
-fn path() -> Result<()> {
-    let path = core::get_current_file()?;
-    if !path.exists() {
+fn path(file: &Path, format: OutputFormat) -> Result<()> {
+    if !file.exists() {
         return Err(Error::msg(
-            "No whatdo file found. Try `wd init` to create one in the current repo",
+            "No whatdo file found. Try `wd init` to create one",
         ));
     }
 
-    print!("{}", path.to_string_lossy());
+    if format == OutputFormat::Json {
+        print_json(json!({ "file": file }));
+        return Ok(());
+    }
+
+    print!("{}", file.to_string_lossy());
     Ok(())
 }
 
 fn main() -> Result<()> {
     env_logger::init();
     let args = Args::parse();
+    let file = core::get_current_file(args.file.as_deref())?;
+    let format = args.format;
+    let push = !args.no_push;
 
     match args.cmd {
-        Some(Command::Path {}) => path(),
+        Some(Command::Path {}) => path(&file, format),
+        Some(Command::Completions { shell }) => {
+            completions::generate(shell, &mut Args::command(), &mut std::io::stdout())
+        }
+        Some(Command::Complete { kind }) => complete(&file, kind),
+        Some(Command::Add {
+            from: Some(from),
+            parent,
+            no_commit,
+            ..
+        }) => add_from(&file, format, &from, parent, no_commit, push),
         Some(Command::Add {
             id,
             tags,
@@ -432,45 +1219,115 @@ fn main() -> Result<()> {
             priority,
             branch_name,
             parent,
+            due,
             start,
             no_commit,
+            ..
         }) => add(
-            id,
+            &file,
+            format,
+            id.ok_or(Error::msg("Expected an ID or --from"))?,
             tags,
             summary,
             priority,
             branch_name,
             parent,
+            due,
             start,
             no_commit,
+            push,
+        ),
+        Some(Command::Edit {
+            id,
+            summary,
+            priority,
+            add_tags,
+            remove_tags,
+            branch_name,
+            due,
+            no_due,
+            no_commit,
+        }) => edit(
+            &file,
+            format,
+            &id,
+            core::WhatdoEdit {
+                summary,
+                priority,
+                add_tags,
+                remove_tags,
+                branch_name,
+                due,
+                remove_due: no_due,
+            },
+            no_commit,
+            push,
         ),
+        Some(Command::Mv {
+            id,
+            parent,
+            new_id,
+            no_commit,
+        }) => mv(&file, format, &id, parent, new_id, no_commit, push),
+        Some(Command::Queue { cmd }) => queue(&file, format, cmd, push),
         Some(Command::Show {
             id,
             tags,
             priorities,
-        }) => show(id, tags, priorities),
+            extra,
+            depth,
+            query,
+            sort,
+        }) => show(
+            &file, format, id, tags, priorities, query, extra, depth, sort,
+        ),
         Some(Command::Next {
             start,
+            start_parent,
+            all,
+            n,
+            tags,
+            priorities,
+            query,
+            sort,
+        }) => next(
+            &file,
+            format,
+            start,
+            start_parent,
             all,
             n,
             tags,
             priorities,
-        }) => next(start, all, n, tags, priorities),
-        Some(Command::Start { id }) => start(&id),
+            query,
+            sort,
+            push,
+        ),
+        Some(Command::Start { id, fast_forward }) => start(&file, format, &id, fast_forward, push),
+        Some(Command::Switch { id, wip }) => switch(&file, format, &id, wip, push),
         Some(Command::Finish {
             no_commit,
             no_merge,
-        }) => finish(no_commit, no_merge),
-        Some(Command::Delete { id, no_commit }) => delete(&id, no_commit),
-        Some(Command::Rm { id, no_commit }) => delete(&id, no_commit),
-        Some(Command::Resolve { id, no_commit }) => resolve(&id, no_commit),
+        }) => finish(&file, format, no_commit, no_merge, push),
+        Some(Command::Delete { id, no_commit }) => delete(&file, format, &id, no_commit, push),
+        Some(Command::Rm { id, no_commit }) => delete(&file, format, &id, no_commit, push),
+        Some(Command::Resolve { id, no_commit }) => resolve(&file, format, &id, no_commit, push),
         Some(Command::Ls {
             id,
             tags,
             priorities,
-        }) => show(id, tags, priorities),
-        Some(Command::Status {}) => status(),
-        Some(Command::Init {}) => init(),
-        None => status(),
+            extra,
+            depth,
+            query,
+            sort,
+        }) => show(
+            &file, format, id, tags, priorities, query, extra, depth, sort,
+        ),
+        Some(Command::Done { cmd }) => done(&file, format, cmd),
+        Some(Command::Reopen { id, no_commit }) => reopen(&file, format, &id, no_commit, push),
+        Some(Command::Status {}) => status(&file, format),
+        Some(Command::Sync {}) => sync(&file, format),
+        Some(Command::Init {}) => init(&file, format),
+        None => status(&file, format),
     }
 }
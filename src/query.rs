@@ -0,0 +1,402 @@
+//! A small query language for filtering whatdos, e.g.
+//! `tag:ui and not tag:optional and priority<=2 and summary~"regex"`.
+//!
+//! Terms are `tag:<tag>`, `id:<id>`, `under:<id>`, `summary~<regex>`,
+//! `priority<op><n>` and `depth<op><n>`, where `<op>` is one of `:`, `=`, `!=`,
+//! `<`, `<=`, `>` and `>=`. Terms can be combined with `and`, `or`, `not` and
+//! parentheses. Adjacent terms without an operator between them are combined
+//! with `and`, and values can be quoted to include spaces or parentheses.
+
+use anyhow::{Error, Result};
+use regex::Regex;
+
+use crate::core::Whatdo;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn compare<T: Ord>(&self, a: T, b: T) -> bool {
+        match self {
+            Comparison::Eq => a == b,
+            Comparison::Ne => a != b,
+            Comparison::Lt => a < b,
+            Comparison::Le => a <= b,
+            Comparison::Gt => a > b,
+            Comparison::Ge => a >= b,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Query {
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Tag(String),
+    Id(String),
+    /// The whatdo is a descendant of the whatdo with this ID
+    Under(String),
+    Summary(Regex),
+    /// Whatdos without a priority never match
+    Priority(Comparison, i64),
+    /// Sub-whatdos of the root have a depth of 1
+    Depth(Comparison, usize),
+}
+
+impl Query {
+    pub fn and(self, other: Query) -> Query {
+        Query::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Query) -> Query {
+        Query::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Replace the IDs in `id:` and `under:` terms, e.g. to resolve `@`
+    pub fn map_ids(self, f: &impl Fn(&str) -> Result<String>) -> Result<Query> {
+        Ok(match self {
+            Query::And(a, b) => a.map_ids(f)?.and(b.map_ids(f)?),
+            Query::Or(a, b) => a.map_ids(f)?.or(b.map_ids(f)?),
+            Query::Not(q) => Query::Not(Box::new(q.map_ids(f)?)),
+            Query::Id(id) => Query::Id(f(&id)?),
+            Query::Under(id) => Query::Under(f(&id)?),
+            query => query,
+        })
+    }
+
+    /// Whether the whatdo satisfies the query. `ancestors` is the IDs of the
+    /// whatdo's ancestors, starting from the root
+    pub fn matches(&self, wd: &Whatdo, ancestors: &[String]) -> bool {
+        match self {
+            Query::And(a, b) => a.matches(wd, ancestors) && b.matches(wd, ancestors),
+            Query::Or(a, b) => a.matches(wd, ancestors) || b.matches(wd, ancestors),
+            Query::Not(q) => !q.matches(wd, ancestors),
+            Query::Tag(tag) => wd.tags.iter().flatten().any(|t| t == tag),
+            Query::Id(id) => &wd.id == id,
+            Query::Under(id) => ancestors.contains(id),
+            Query::Summary(re) => re.is_match(&wd.summary()),
+            Query::Priority(cmp, n) => wd.priority.is_some_and(|p| cmp.compare(p, *n)),
+            Query::Depth(cmp, n) => cmp.compare(ancestors.len(), *n),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Word { text: String, quoted: bool },
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(Token::Open);
+        } else if c == ')' {
+            chars.next();
+            tokens.push(Token::Close);
+        } else {
+            let mut text = String::new();
+            let mut quoted = false;
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+                chars.next();
+                if c != '"' {
+                    text.push(c);
+                    continue;
+                }
+
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        None => return Err(Error::msg("Unterminated quote in query")),
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            None => return Err(Error::msg("Unterminated quote in query")),
+                            Some(escaped) => text.push(escaped),
+                        },
+                        Some(c) => text.push(c),
+                    }
+                }
+            }
+            tokens.push(Token::Word { text, quoted });
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_comparison(s: &str) -> Option<(Comparison, &str)> {
+    // Two-character operators first so that `<=` isn't read as `<`
+    [
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("!=", Comparison::Ne),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+        ("=", Comparison::Eq),
+        (":", Comparison::Eq),
+    ]
+    .into_iter()
+    .find_map(|(op, cmp)| s.strip_prefix(op).map(|rest| (cmp, rest)))
+}
+
+fn parse_term(word: &str) -> Result<Query> {
+    let field_end = word
+        .find(|c: char| ":~<>=!".contains(c))
+        .ok_or(Error::msg(format!(
+            "Expected a term like 'tag:<tag>' in query, got '{}'",
+            word
+        )))?;
+    let (field, rest) = word.split_at(field_end);
+
+    let value = |prefix: &str| {
+        rest.strip_prefix(prefix)
+            .filter(|value| !value.is_empty())
+            .ok_or(Error::msg(format!(
+                "Expected '{}{}<value>' in query, got '{}'",
+                field, prefix, word
+            )))
+    };
+    let number = || -> Result<(Comparison, i64)> {
+        let (cmp, value) = parse_comparison(rest).ok_or(Error::msg(format!(
+            "Expected a comparison like '{}<=2' in query, got '{}'",
+            field, word
+        )))?;
+        let n = value.parse().map_err(|_| {
+            Error::msg(format!(
+                "Expected an integer after '{}' in query, got '{}'",
+                field, word
+            ))
+        })?;
+        Ok((cmp, n))
+    };
+
+    match field {
+        "tag" => Ok(Query::Tag(value(":")?.to_owned())),
+        "id" => Ok(Query::Id(value(":")?.to_owned())),
+        "under" => Ok(Query::Under(value(":")?.to_owned())),
+        "summary" => {
+            let re = value("~")?;
+            Regex::new(re)
+                .map(Query::Summary)
+                .map_err(|e| Error::msg(format!("Invalid regex '{}' in query: {}", re, e)))
+        }
+        "priority" => {
+            let (cmp, n) = number()?;
+            Ok(Query::Priority(cmp, n))
+        }
+        "depth" => {
+            let (cmp, n) = number()?;
+            if n < 0 {
+                return Err(Error::msg(format!(
+                    "Expected depth to be non-negative in query, got '{}'",
+                    word
+                )));
+            }
+            Ok(Query::Depth(cmp, n as usize))
+        }
+        _ => Err(Error::msg(format!(
+            "Unknown field '{}' in query. Expected one of: tag, id, under, summary, priority, depth",
+            field
+        ))),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word { text, quoted: false }) if text == keyword)
+    }
+
+    fn parse_or(&mut self) -> Result<Query> {
+        let mut query = self.parse_and()?;
+        while self.peek_keyword("or") {
+            self.position += 1;
+            query = query.or(self.parse_and()?);
+        }
+        Ok(query)
+    }
+
+    fn parse_and(&mut self) -> Result<Query> {
+        let mut query = self.parse_not()?;
+        loop {
+            if self.peek_keyword("and") {
+                self.position += 1;
+            } else if self.peek().is_none()
+                || self.peek() == Some(&Token::Close)
+                || self.peek_keyword("or")
+            {
+                return Ok(query);
+            }
+            query = query.and(self.parse_not()?);
+        }
+    }
+
+    fn parse_not(&mut self) -> Result<Query> {
+        if self.peek_keyword("not") {
+            self.position += 1;
+            return Ok(Query::Not(Box::new(self.parse_not()?)));
+        }
+
+        let token = self.peek().cloned();
+        self.position += 1;
+        match token {
+            None => Err(Error::msg("Unexpected end of query")),
+            Some(Token::Close) => Err(Error::msg("Unexpected ')' in query")),
+            Some(Token::Open) => {
+                let query = self.parse_or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(Error::msg("Expected ')' in query"));
+                }
+                self.position += 1;
+                Ok(query)
+            }
+            Some(Token::Word { text, .. }) => parse_term(&text),
+        }
+    }
+}
+
+pub fn parse(s: &str) -> Result<Query> {
+    let mut parser = Parser {
+        tokens: tokenize(s)?,
+        position: 0,
+    };
+    let query = parser.parse_or()?;
+    if parser.peek().is_some() {
+        return Err(Error::msg("Unexpected ')' in query"));
+    }
+    Ok(query)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn whatdo(id: &str, priority: Option<i64>, tags: &[&str]) -> Whatdo {
+        Whatdo {
+            priority,
+            tags: Some(tags.iter().map(|t| t.to_string()).collect::<Vec<_>>())
+                .filter(|t| !t.is_empty()),
+            simple_format: false,
+            ..Whatdo::simple(id, Some(format!("The {} whatdo", id)))
+        }
+    }
+
+    fn matches(query: &str, wd: &Whatdo, ancestors: &[&str]) -> bool {
+        let ancestors: Vec<String> = ancestors.iter().map(|a| a.to_string()).collect();
+        parse(query).unwrap().matches(wd, &ancestors)
+    }
+
+    #[test]
+    fn test_terms() {
+        let wd = whatdo("button", Some(2), &["ui", "optional"]);
+        let ancestors = ["root", "cli-enhancements", "colors"];
+        assert!(matches("tag:ui", &wd, &ancestors));
+        assert!(!matches("tag:backend", &wd, &ancestors));
+        assert!(matches("id:button", &wd, &ancestors));
+        assert!(matches("under:cli-enhancements", &wd, &ancestors));
+        assert!(!matches("under:button", &wd, &ancestors));
+        assert!(matches("priority<=2", &wd, &ancestors));
+        assert!(matches("priority:2", &wd, &ancestors));
+        assert!(!matches("priority<2", &wd, &ancestors));
+        assert!(!matches("priority!=2", &wd, &ancestors));
+        assert!(matches("depth>=3", &wd, &ancestors));
+        assert!(!matches("depth=2", &wd, &ancestors));
+        assert!(matches("summary~\"^The b\"", &wd, &ancestors));
+        assert!(!matches("summary~whatever", &wd, &ancestors));
+
+        let unprioritized = whatdo("unprioritized", None, &[]);
+        assert!(!matches("priority>=0", &unprioritized, &[]));
+        assert!(!matches("priority<0", &unprioritized, &[]));
+    }
+
+    #[test]
+    fn test_operators() {
+        let wd = whatdo("button", Some(2), &["ui", "optional"]);
+        assert!(!matches("tag:ui and not tag:optional", &wd, &[]));
+        assert!(matches("tag:ui not tag:backend", &wd, &[]));
+        assert!(matches("tag:backend or tag:ui", &wd, &[]));
+        // `and` binds tighter than `or`
+        assert!(matches("tag:ui or tag:backend and tag:missing", &wd, &[]));
+        assert!(!matches(
+            "(tag:ui or tag:backend) and tag:missing",
+            &wd,
+            &[]
+        ));
+        assert!(matches("not not tag:ui", &wd, &[]));
+        assert!(matches("summary~\"(button|link)\"", &wd, &[]));
+    }
+
+    #[test]
+    fn test_map_ids() {
+        let query = parse("id:@ or not under:@^")
+            .unwrap()
+            .map_ids(&|id| Ok(id.replace('@', "button")))
+            .unwrap();
+        let wd = whatdo("button", None, &[]);
+        assert!(query.matches(&wd, &[]));
+        assert!(!query.matches(&whatdo("link", None, &[]), &[String::from("button^")]));
+        assert!(parse("id:@")
+            .unwrap()
+            .map_ids(&|_| Err(Error::msg("No current whatdo")))
+            .is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        for query in [
+            "",
+            "tag:",
+            "tag",
+            "color:red",
+            "priority<=high",
+            "depth<-1",
+            "summary~\"(\"",
+            "(tag:ui",
+            "tag:ui)",
+            "tag:ui and",
+            "summary~\"unterminated",
+        ] {
+            assert!(parse(query).is_err(), "{} should not parse", query);
+        }
+        assert_eq!(
+            tokenize("tag:\"a b\" (x)").unwrap(),
+            vec![
+                Token::Word {
+                    text: String::from("tag:a b"),
+                    quoted: true
+                },
+                Token::Open,
+                Token::Word {
+                    text: String::from("x"),
+                    quoted: false
+                },
+                Token::Close,
+            ]
+        );
+    }
+}
@@ -1,111 +1,98 @@
+#[cfg(test)]
 use crate::core::Whatdo;
 
+/// Content of the whatdo file created by `wd init`, with comments explaining
+/// the format
+pub const INITIAL_WHATDO_FILE: &str = r#"summary: <description of your project>
+# The 'queue' is a manually set sequence of whatdos to complete first
+queue:
+- setting-up-new-project
+whatdos:
+  # Every whatdo is either a leaf whatdo or has sub-whatdos.
+  # This whatdo, called "setting-up-new-project", has sub-whatdos.
+  # `wd next` traverses this tree to determine what you should do next
+  # based on the queue, the order of sub-whatdos, priority, and tag filters.
+  setting-up-new-project:
+    summary: Things to do to set up your WHATDO.yaml for a project
+    # Use priority to influence the output of `wd next`.
+    # Lower-numbered priority whatdos are ordered before high-numbered ones
+    priority: 1
+    whatdos:
+      run-start-command:
+        summary: Start this interactive tutorial with `wd start setting-up-new-project`
+      use-next-command:
+        summary: View what to do next with `wd next`, or view the whole whatdo tree with `wd ls`
+      add-with-cli:
+        summary: 'Add some real whatdos: `wd add example-whatdo-id -m "Long form description of what to do"`'
+      # A whatdo can be just a summary
+      add-manually: Add abbreviated whatdos like this by manually editing this file
+      use-tags:
+        summary: 'Classify whatdos with tags and priorities: `wd add test-tags --tags important,cool -p 1'
+        priority: 2
+        # `wd ls --tags optional` would output this whatdo and the one below it
+        tags:
+        - optional
+      nest:
+        summary: 'Nest whatdos: `wd add sub-whatdo --parent example-whatdo-id`'
+        tags:
+        - optional
+      run-finish-command:
+        summary: 'Finish this tutorial and merge changes to the default branch: `wd finish`'
+"#;
+
+/// The whatdo tree that `INITIAL_WHATDO_FILE` is expected to parse to
+#[cfg(test)]
 pub fn initial_whatdo_file() -> Whatdo {
-    Whatdo {
-        id: String::from("root"),
+    let whatdo = |id: &str, summary: &str| Whatdo {
         simple_format: false,
-        summary: Some(String::from("<description of your project>")),
+        ..Whatdo::simple(id, Some(summary))
+    };
+    Whatdo {
         queue: Some(vec![String::from("setting-up-new-project")]),
-        priority: None,
-        tags: None,
-        branch_name: None,
-        whatdos: Some(vec![
-            Whatdo {
-                id: String::from("setting-up-new-project"),
-                priority: Some(1),
-                queue: None,
-                summary: Some(String::from(
-                    "Things to do to set up your WHATDO.yaml for a project",
-                )),
-                tags: None,
-                branch_name: None,
-                simple_format: false,
-                whatdos: Some(vec![
-                    Whatdo {
-                        id: String::from("run-start-command"),
-                        priority: None,
-                        queue: None,
-                        summary: Some(String::from(
-                            "Start this interactive tutorial with `wd start setting-up-new-project`",
-                        )),
-                        tags: None,
-                        whatdos: None,
-                        branch_name: None,
-                        simple_format: false,
-                    },
-                    Whatdo {
-                        id: String::from("use-next-command"),
-                        priority: None,
-                        queue: None,
-                        summary: Some(String::from(
-                            "View what to do next with `wd next`, or view the whole whatdo tree with `wd ls`",
-                        )),
-                        tags: None,
-                        whatdos: None,
-                        branch_name: None,
-                        simple_format: false,
-                    },
-                    Whatdo {
-                    id: String::from("add-with-cli"),
-                    priority: None,
-                    queue: None,
-                    summary: Some(String::from(
-                        "Add some real whatdos: `wd add example-whatdo-id -m \"Long form description of what to do\"`",
-                    )),
-                    tags: None,
-                    whatdos: None,
-                    branch_name: None,
-                    simple_format: false,
-                },
-                Whatdo {
-                    id: String::from("add-manually"),
-                    priority: None,
-                    queue: None,
-                    summary: Some(String::from(
-                        "Add abbreviated whatdos like this by manually editing this file",
-                    )),
-                    tags: None,
-                    whatdos: None,
-                    branch_name: None,
-                    simple_format: true,
-                },
+        whatdos: Some(vec![Whatdo {
+            priority: Some(1),
+            whatdos: Some(vec![
+                whatdo(
+                    "run-start-command",
+                    "Start this interactive tutorial with `wd start setting-up-new-project`",
+                ),
+                whatdo(
+                    "use-next-command",
+                    "View what to do next with `wd next`, or view the whole whatdo tree with `wd ls`",
+                ),
+                whatdo(
+                    "add-with-cli",
+                    "Add some real whatdos: `wd add example-whatdo-id -m \"Long form description of what to do\"`",
+                ),
+                Whatdo::simple(
+                    "add-manually",
+                    Some("Add abbreviated whatdos like this by manually editing this file"),
+                ),
                 Whatdo {
-                    id: String::from("use-tags"),
                     priority: Some(2),
-                    queue: None,
-                    summary: Some(String::from(
-                        "Classify whatdos with tags and priorities: `wd add test-tags --tags important,cool -p 1",
-                    )),
                     tags: Some(vec![String::from("optional")]),
-                    whatdos: None,
-                    branch_name: None,
-                    simple_format: false,
+                    ..whatdo(
+                        "use-tags",
+                        "Classify whatdos with tags and priorities: `wd add test-tags --tags important,cool -p 1",
+                    )
                 },
                 Whatdo {
-                    id: String::from("nest"),
-                    priority: None,
-                    queue: None,
-                    summary: Some(String::from(
-                        "Nest whatdos: `wd add sub-whatdo --parent example-whatdo-id`",
-                    )),
                     tags: Some(vec![String::from("optional")]),
-                    whatdos: None,
-                    branch_name: None,
-                    simple_format: false,
+                    ..whatdo(
+                        "nest",
+                        "Nest whatdos: `wd add sub-whatdo --parent example-whatdo-id`",
+                    )
                 },
-                Whatdo {
-                    id: String::from("run-finish-command"),
-                    priority: None,
-                    queue: None,
-                    summary: Some(String::from(
-                        "Finish this tutorial and merge changes to the default branch: `wd finish`",
-                    )),
-                    tags: None,
-                    whatdos: None,
-                    branch_name: None,
-                    simple_format: false,
-                }]),
-            }
-        ])
+                whatdo(
+                    "run-finish-command",
+                    "Finish this tutorial and merge changes to the default branch: `wd finish`",
+                ),
+            ]),
+            ..whatdo(
+                "setting-up-new-project",
+                "Things to do to set up your WHATDO.yaml for a project",
+            )
+        }]),
+        ..whatdo("root", "<description of your project>")
     }
 }
@@ -0,0 +1,387 @@
+//! Minimal editing of block-style YAML documents. Updating a document only
+//! rewrites the mapping entries whose values changed, so comments, blank lines,
+//! quoting and key order everywhere else are left untouched.
+
+use anyhow::Result;
+use serde_yaml::{Mapping, Value};
+
+/// A `key: value` entry of a block mapping
+struct Entry {
+    key: Value,
+    /// Index of the line containing the key
+    start: usize,
+    /// Index one past the last line of the value, not including trailing
+    /// blank lines or comments that aren't indented under the key
+    end: usize,
+    /// Whether the value starts on the line after the key
+    block: bool,
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_trivia(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+fn is_sequence_item(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed == "-" || trimmed.starts_with("- ")
+}
+
+/// Index of the `:` ending a quoted key that starts at the beginning of `s`
+fn quoted_key_end(s: &str) -> Option<usize> {
+    let quote = s.chars().next()?;
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if quote == '"' && c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            // A doubled single quote is an escaped quote, and is handled by
+            // treating the second quote as the start of a new quoted span
+            let rest = &s[i + 1..];
+            let after = rest.trim_start_matches(' ');
+            if after.starts_with(':') {
+                return Some(s.len() - after.len());
+            }
+            if !rest.starts_with(quote) {
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// Parse the key of a block mapping entry at exactly the given indentation
+fn parse_key_line(line: &str, indent: usize) -> Option<(Value, &str)> {
+    if indentation(line) != indent || is_trivia(line) || is_sequence_item(line) {
+        return None;
+    }
+
+    let content = &line[indent..];
+    let colon = match content.chars().next()? {
+        '"' | '\'' => quoted_key_end(content)?,
+        '{' | '[' | '?' | '&' | '*' | '!' | '|' | '>' => return None,
+        _ => content.char_indices().find_map(|(i, c)| {
+            let next = content[i + 1..].chars().next();
+            (c == ':' && (next.is_none() || next == Some(' '))).then_some(i)
+        })?,
+    };
+
+    let key: Value = serde_yaml::from_str(content[..colon].trim_end()).ok()?;
+    Some((key, &content[colon + 1..]))
+}
+
+/// Find the entries of the block mapping with the given indentation in
+/// `lines[lo..hi]`
+fn scan_entries(lines: &[&str], lo: usize, hi: usize, indent: usize) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut i = lo;
+    while i < hi {
+        let (key, rest) = match parse_key_line(lines[i], indent) {
+            Some(parsed) => parsed,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        let rest = rest.trim();
+        let block = rest.is_empty() || rest.starts_with('#');
+
+        let mut last = i;
+        let mut j = i + 1;
+        while j < hi {
+            let line = lines[j];
+            let line_indent = indentation(line);
+            if line.trim().is_empty()
+                || (line.trim_start().starts_with('#') && line_indent <= indent)
+            {
+                // Might belong to this entry or the next one
+            } else if line_indent > indent || (block && is_sequence_item(line)) {
+                last = j;
+            } else {
+                break;
+            }
+            j += 1;
+        }
+
+        entries.push(Entry {
+            key,
+            start: i,
+            end: last + 1,
+            block,
+        });
+        i = last + 1;
+    }
+    entries
+}
+
+/// Render `key: value` with every line indented by the given amount
+fn render_entry(key: &Value, value: &Value, indent: usize) -> Result<Vec<String>> {
+    let mut mapping = Mapping::new();
+    mapping.insert(key.clone(), value.clone());
+    let rendered = serde_yaml::to_string(&mapping)?;
+    Ok(rendered
+        .lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{:indent$}{}", "", line, indent = indent)
+            }
+        })
+        .collect())
+}
+
+/// Rewrite the block mapping with the given indentation in `lines[lo..hi]` from
+/// `old` to `new`. Returns None if the lines don't look like `old`
+fn edit_mapping(
+    lines: &[&str],
+    lo: usize,
+    hi: usize,
+    indent: usize,
+    old: &Mapping,
+    new: &Mapping,
+) -> Result<Option<Vec<String>>> {
+    let entries = scan_entries(lines, lo, hi, indent);
+    if entries.len() != old.len() || entries.iter().any(|e| !old.contains_key(&e.key)) {
+        return Ok(None);
+    }
+
+    // Position new keys after the closest preceding key that already exists
+    let mut inserted_after: Vec<Vec<(&Value, &Value)>> = vec![Vec::new(); entries.len() + 1];
+    let mut previous = 0;
+    for (k, v) in new {
+        match entries.iter().position(|e| &e.key == k) {
+            Some(position) => previous = position + 1,
+            None => inserted_after[previous].push((k, v)),
+        }
+    }
+
+    let mut result: Vec<String> = Vec::new();
+    let mut previous_end = lo;
+    for (position, entry) in entries.iter().enumerate() {
+        let leading = &lines[previous_end..entry.start];
+        previous_end = entry.end;
+
+        match new.get(&entry.key) {
+            None => {
+                // Comments directly above a removed entry are removed with it,
+                // as is a blank line separating it from the next entry
+                let mut kept = leading
+                    .iter()
+                    .rposition(|l| l.trim().is_empty())
+                    .map_or(0, |i| i + 1);
+                if entry.end < hi && lines[entry.end].trim().is_empty() {
+                    while kept > 0 && leading[kept - 1].trim().is_empty() {
+                        kept -= 1;
+                    }
+                }
+                result.extend(leading[..kept].iter().map(|l| l.to_string()));
+                if position == 0 {
+                    for (k, v) in &inserted_after[0] {
+                        result.append(&mut render_entry(k, v, indent)?);
+                    }
+                }
+            }
+            Some(new_value) => {
+                result.extend(leading.iter().map(|l| l.to_string()));
+                if position == 0 {
+                    for (k, v) in &inserted_after[0] {
+                        result.append(&mut render_entry(k, v, indent)?);
+                    }
+                }
+
+                let old_value = &old[&entry.key];
+                if old_value == new_value {
+                    result.extend(lines[entry.start..entry.end].iter().map(|l| l.to_string()));
+                } else {
+                    result.append(&mut edit_entry(lines, entry, indent, old_value, new_value)?);
+                }
+            }
+        }
+
+        for (k, v) in &inserted_after[position + 1] {
+            result.append(&mut render_entry(k, v, indent)?);
+        }
+    }
+
+    if entries.is_empty() {
+        result.extend(lines[lo..hi].iter().map(|l| l.to_string()));
+        for (k, v) in &inserted_after[0] {
+            result.append(&mut render_entry(k, v, indent)?);
+        }
+    } else {
+        result.extend(lines[previous_end..hi].iter().map(|l| l.to_string()));
+    }
+
+    Ok(Some(result))
+}
+
+fn edit_entry(
+    lines: &[&str],
+    entry: &Entry,
+    indent: usize,
+    old: &Value,
+    new: &Value,
+) -> Result<Vec<String>> {
+    if let (Value::Mapping(old), Value::Mapping(new), true) = (old, new, entry.block) {
+        let first_content = (entry.start + 1..entry.end).find(|i| !is_trivia(lines[*i]));
+        if let Some(first_content) = first_content {
+            let child_indent = indentation(lines[first_content]);
+            if child_indent > indent {
+                let edited =
+                    edit_mapping(lines, entry.start + 1, entry.end, child_indent, old, new)?;
+                if let Some(mut edited) = edited {
+                    let mut result = vec![lines[entry.start].to_owned()];
+                    result.append(&mut edited);
+                    return Ok(result);
+                }
+            }
+        }
+    }
+
+    render_entry(&entry.key, new, indent)
+}
+
+/// Return the given YAML document updated to represent `value`, changing as
+/// little of the text as possible
+pub fn update(text: &str, value: &Value) -> Result<String> {
+    let old: Value = serde_yaml::from_str(text)?;
+    if &old == value {
+        return Ok(text.to_owned());
+    }
+
+    let lines: Vec<&str> = text.lines().collect();
+    let edited = match (&old, value) {
+        (Value::Mapping(old), Value::Mapping(new)) => {
+            let root_is_block = lines
+                .iter()
+                .find(|l| !is_trivia(l) && !l.starts_with("---") && !l.starts_with('%'))
+                .is_some_and(|l| parse_key_line(l, 0).is_some());
+            if root_is_block {
+                edit_mapping(&lines, 0, lines.len(), 0, old, new)?
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+
+    if let Some(edited) = edited {
+        let mut result = edited.join("\n");
+        if text.ends_with('\n') {
+            result.push('\n');
+        }
+
+        // Only keep the edit if it actually produced the right document
+        if serde_yaml::from_str::<Value>(&result).ok().as_ref() == Some(value) {
+            return Ok(result);
+        }
+    }
+
+    Ok(serde_yaml::to_string(value)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const DOCUMENT: &str = "\
+# The summary of the project
+summary: A project
+queue: [a]
+whatdos:
+  # The first whatdo
+  a:
+    summary: 'First: the beginning'
+    priority: 1
+
+  # The second whatdo
+  b: Second
+  c:
+    summary: |
+      Third
+      and last
+";
+
+    fn edited(f: impl Fn(&mut Mapping)) -> String {
+        let mut value: Value = serde_yaml::from_str(DOCUMENT).unwrap();
+        f(value.as_mapping_mut().unwrap());
+        let result = update(DOCUMENT, &value).unwrap();
+        assert_eq!(serde_yaml::from_str::<Value>(&result).unwrap(), value);
+        result
+    }
+
+    fn whatdos(mapping: &mut Mapping) -> &mut Mapping {
+        mapping
+            .get_mut("whatdos")
+            .unwrap()
+            .as_mapping_mut()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_unchanged() {
+        assert_eq!(edited(|_| {}), DOCUMENT);
+    }
+
+    #[test]
+    fn test_add_entry() {
+        assert_eq!(
+            edited(|m| {
+                whatdos(m).insert(Value::from("d"), Value::from("Fourth"));
+            }),
+            DOCUMENT.to_owned() + "  d: Fourth\n"
+        );
+    }
+
+    #[test]
+    fn test_add_key_after_previous() {
+        assert_eq!(
+            edited(|m| {
+                let b = whatdos(m).get_mut("c").unwrap().as_mapping_mut().unwrap();
+                b.insert(Value::from("tags"), Value::from(vec!["x"]));
+            }),
+            DOCUMENT.to_owned() + "    tags:\n    - x\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_entry() {
+        assert_eq!(
+            edited(|m| {
+                whatdos(m).remove("b");
+            }),
+            DOCUMENT.replace("\n  # The second whatdo\n  b: Second", "")
+        );
+        assert_eq!(
+            edited(|m| {
+                whatdos(m).remove("a");
+            }),
+            DOCUMENT.replace(
+                "  # The first whatdo\n  a:\n    summary: 'First: the beginning'\n    priority: 1\n",
+                ""
+            )
+        );
+    }
+
+    #[test]
+    fn test_change_value() {
+        assert_eq!(
+            edited(|m| {
+                let a = whatdos(m).get_mut("a").unwrap().as_mapping_mut().unwrap();
+                a.insert(Value::from("priority"), Value::from(2));
+                m.insert(Value::from("queue"), Value::from(Vec::<String>::new()));
+            }),
+            DOCUMENT
+                .replace("priority: 1", "priority: 2")
+                .replace("queue: [a]", "queue: []")
+        );
+    }
+}